@@ -7,6 +7,8 @@ import (
 	"fmt"
 	"log"
 	"os"
+	"strconv"
+	"time"
 
 	"github.com/tetratelabs/wazero"
 	"github.com/tetratelabs/wazero/api"
@@ -39,9 +41,12 @@ func main() {
 		WithStdout(stdout).WithStderr(os.Stderr)
 
 	// Instantiate a Go-defined module named "env" that exports a function to
-	// log to the console.
+	// log to the console, one giving the current time, and one answering
+	// external data requests.
 	_, err := r.NewModuleBuilder("env").
 		ExportFunction("log", logString).
+		ExportFunction("now", now).
+		ExportFunction("external_data", externalData).
 		Instantiate(ctx, r)
 	if err != nil {
 		log.Panicln(err)
@@ -58,11 +63,20 @@ func main() {
 	if err != nil {
 		log.Panicln(err)
 	}
-	mod, err := r.InstantiateModule(ctx, code, config.WithArgs("wasi", os.Args[1], os.Args[2]))
+	// The object, the parameters and the optional inventory.
+	mod, err := r.InstantiateModule(ctx, code, config.WithArgs(append([]string{"wasi"}, os.Args[1:]...)...))
 	if err != nil {
 		log.Panicln(err)
 	}
 
+	// POLICY_LOG_LEVEL chooses the most verbose level the module logs, from
+	// 0 for error to 3 for debug.
+	if level, err := strconv.ParseUint(os.Getenv("POLICY_LOG_LEVEL"), 10, 32); err == nil {
+		if _, err = mod.ExportedFunction("set_log_level").Call(ctx, level); err != nil {
+			log.Panicln(err)
+		}
+	}
+
 	// Get references to WebAssembly functions we'll use in this example.
 	eval := mod.ExportedFunction("eval")
 
@@ -75,6 +89,28 @@ func main() {
 	fmt.Println("host getting data from guest stdout: " + string(decision))
 }
 
+// now returns the current time in seconds since the Unix epoch.
+func now() int64 {
+	return time.Now().Unix()
+}
+
+// externalData answers the external data requests of the module. This host
+// has no provider, so every request gets a system error. The response is
+// written to a buffer from the module's allocate export, which the module
+// frees.
+func externalData(ctx context.Context, m api.Module, offset, byteCount uint32) uint64 {
+	response := []byte(`{"systemError":"no external data provider"}`)
+	results, err := m.ExportedFunction("allocate").Call(ctx, uint64(len(response)))
+	if err != nil {
+		log.Panicln(err)
+	}
+	ptr := uint32(results[0])
+	if !m.Memory().Write(ctx, ptr, response) {
+		log.Panicf("Memory.Write(%d, %d) out of range", ptr, len(response))
+	}
+	return uint64(ptr)<<32 | uint64(len(response))
+}
+
 func logString(ctx context.Context, m api.Module, offset, byteCount uint32) {
 	buf, ok := m.Memory().Read(ctx, offset, byteCount)
 	if !ok {
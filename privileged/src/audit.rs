@@ -0,0 +1,202 @@
+//! Audits existing objects, like the Gatekeeper audit does for a cluster, but
+//! from a snapshot of the objects instead of a live API server.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use policy_sdk::{Decision, EnforcementAction, Inventory, Request};
+use serde::Serialize;
+
+use crate::{exemption, is_exempt, validate_pod, workload, Parameters};
+
+/// Rule under which violations not attributed to a rule are reported, e.g.
+/// for objects whose pod spec cannot be read.
+pub const INVALID_OBJECT: &str = "invalidObject";
+
+/// The violations found by an audit.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    /// Number of pods and workloads audited, other objects are ignored.
+    pub audited_objects: usize,
+    pub total_violations: usize,
+    /// The violations by namespace, then by rule. Cluster scoped objects are
+    /// under the empty namespace.
+    pub namespaces: BTreeMap<String, BTreeMap<String, Vec<AuditViolation>>>,
+}
+
+/// A violation of an existing object, with the fields of the violations in
+/// the status of Gatekeeper constraints.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditViolation {
+    pub kind: String,
+    pub name: String,
+    pub message: String,
+    pub enforcement_action: EnforcementAction,
+}
+
+impl Report {
+    /// Audits an object, see [`audit`]. Namespaces are exempted by label
+    /// according to the inventory of the request, if any.
+    pub fn add(&mut self, request: &Request, params: &Parameters) {
+        if is_exempt(request, params) {
+            return;
+        }
+        let metadata = &request.object()["metadata"];
+        let namespace = metadata["namespace"].as_str().unwrap_or_default();
+        let decision = match workload::pod_spec(request.object()) {
+            Some(Ok(pod_spec)) => {
+                let exemptions = exemption::granted(request.object(), params, exemption::today());
+                let exempt_containers = exemption::containers(&exemptions);
+                validate_pod(&pod_spec, request.namespace(), params, &exempt_containers)
+            }
+            Some(Err(e)) => Decision::deny(e.to_string()),
+            None => return,
+        };
+        self.audited_objects += 1;
+
+        for violation in decision.violations {
+            self.total_violations += 1;
+            self.namespaces
+                .entry(namespace.to_string())
+                .or_default()
+                .entry(violation.rule.unwrap_or_else(|| INVALID_OBJECT.to_string()))
+                .or_default()
+                .push(AuditViolation {
+                    kind: request.kind().unwrap_or_default().to_string(),
+                    name: metadata["name"].as_str().unwrap_or_default().to_string(),
+                    message: violation.message,
+                    enforcement_action: violation.enforcement_action,
+                });
+        }
+    }
+}
+
+/// Runs [`validate_pod`] on the pods and the workloads among the objects,
+/// except in the exempted namespaces and on the containers exempted by
+/// annotation. Without an inventory, the labels of the namespaces are read
+/// from the Namespace objects among the objects, as admission reads them
+/// from the inventory.
+pub fn audit<'a>(requests: impl IntoIterator<Item = &'a Request>, params: &Parameters) -> Report {
+    let requests: Vec<&Request> = requests.into_iter().collect();
+    let namespaces = Arc::new(namespaces(&requests));
+    let mut report = Report::default();
+    for request in requests {
+        match request.inventory() {
+            Some(_) => report.add(request, params),
+            None => {
+                let request = request.clone().with_inventory(Arc::clone(&namespaces));
+                report.add(&request, params);
+            }
+        }
+    }
+    report
+}
+
+/// Returns the Namespace objects of the snapshot as an inventory. Namespaces
+/// without an `apiVersion` or a name are left out, their labels can't be
+/// looked up anyway.
+fn namespaces(requests: &[&Request]) -> Inventory {
+    let mut inventory = Inventory::default();
+    for request in requests {
+        if request.kind() == Some("Namespace") {
+            let _ = inventory.add(request.object().clone());
+        }
+    }
+    inventory
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn audit_groups_violations_by_namespace_and_rule_test() -> Result<()> {
+        let requests = Request::from_documents(
+            r#"{
+  "apiVersion": "v1",
+  "kind": "List",
+  "items": [
+    {"kind": "Pod", "metadata": {"name": "web", "namespace": "prod"},
+     "spec": {"hostPID": true, "containers": [
+       {"name": "nginx", "image": "nginx", "securityContext": {"privileged": true}}]}},
+    {"kind": "DaemonSet", "metadata": {"name": "agent", "namespace": "kube-system"},
+     "spec": {"template": {"spec": {"containers": [
+       {"name": "agent", "image": "agent", "securityContext": {"privileged": true}}]}}}},
+    {"kind": "Deployment", "metadata": {"name": "api", "namespace": "prod"},
+     "spec": {"template": {"spec": {"containers": [{"name": "api", "image": "api"}]}}}},
+    {"kind": "Service", "metadata": {"name": "web", "namespace": "prod"}, "spec": {}}
+  ]
+}"#,
+        )?;
+        let params: Parameters = policy_sdk::parse_parameters(
+            r#"{"rules": {"hostNamespaces": {"enabled": true, "enforcementAction": "warn"}}}"#,
+        )?;
+
+        let exempt: Parameters =
+            policy_sdk::parse_parameters(r#"{"exemptNamespaces": ["kube-*"]}"#)?;
+        let report = audit(&requests, &exempt);
+        assert_eq!(report.audited_objects, 2);
+        assert!(!report.namespaces.contains_key("kube-system"));
+
+        let report = audit(&requests, &params);
+        assert_eq!(report.audited_objects, 3, "The Service must be ignored");
+        assert_eq!(report.total_violations, 3);
+
+        let prod = &report.namespaces["prod"];
+        assert_eq!(
+            prod.keys().collect::<Vec<_>>(),
+            vec!["hostNamespaces", "privileged"]
+        );
+        assert_eq!(
+            prod["privileged"],
+            vec![AuditViolation {
+                kind: "Pod".to_string(),
+                name: "web".to_string(),
+                message: "Privileged container is not allowed: nginx".to_string(),
+                enforcement_action: EnforcementAction::Deny,
+            }]
+        );
+        assert_eq!(
+            prod["hostNamespaces"][0].enforcement_action,
+            EnforcementAction::Warn,
+            "Violations of every action must be reported"
+        );
+        assert_eq!(report.namespaces["kube-system"]["privileged"].len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn audit_exempts_namespaces_by_label_test() -> Result<()> {
+        let requests = Request::from_documents(
+            r#"{
+  "apiVersion": "v1",
+  "kind": "List",
+  "items": [
+    {"apiVersion": "v1", "kind": "Namespace",
+     "metadata": {"name": "ci", "labels": {"privileged-allowed": "true"}}},
+    {"apiVersion": "v1", "kind": "Namespace", "metadata": {"name": "prod"}},
+    {"kind": "Pod", "metadata": {"name": "runner", "namespace": "ci"},
+     "spec": {"containers": [
+       {"name": "runner", "image": "runner", "securityContext": {"privileged": true}}]}},
+    {"kind": "Pod", "metadata": {"name": "web", "namespace": "prod"},
+     "spec": {"containers": [
+       {"name": "nginx", "image": "nginx", "securityContext": {"privileged": true}}]}}
+  ]
+}"#,
+        )?;
+        let params: Parameters = policy_sdk::parse_parameters(
+            r#"{"namespaceLabels": {"exempt": {"privileged-allowed": "true"}}}"#,
+        )?;
+        let report = audit(&requests, &params);
+        assert_eq!(report.audited_objects, 1);
+        assert!(
+            !report.namespaces.contains_key("ci"),
+            "A namespace exempted by label at admission must be exempted by the audit"
+        );
+        assert_eq!(report.namespaces["prod"]["privileged"].len(), 1);
+        Ok(())
+    }
+}
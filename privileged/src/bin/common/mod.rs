@@ -0,0 +1,100 @@
+//! Command-line handling shared by `privileged-eval` and `privileged-audit`.
+
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
+use anyhow::{anyhow, Context, Result};
+use policy_sdk::parse_parameters;
+use privileged::Parameters;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Human,
+    Json,
+}
+
+pub struct Options {
+    pub parameters: Parameters,
+    pub output: Output,
+    /// The files to read, `-` standing for stdin.
+    pub inputs: Vec<String>,
+}
+
+/// Parses `[--parameters <file>] [--output human|json] <input>...`.
+pub fn parse_args(usage: &str) -> Result<Options> {
+    let mut parameters = None;
+    let mut options = Options {
+        parameters: Parameters::default(),
+        output: Output::Human,
+        inputs: Vec::new(),
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--parameters" | "-p" => {
+                parameters = Some(args.next().ok_or_else(|| anyhow!("{}", usage))?)
+            }
+            "--output" | "-o" => {
+                options.output = match args.next().as_deref() {
+                    Some("human") => Output::Human,
+                    Some("json") => Output::Json,
+                    _ => return Err(anyhow!("{}", usage)),
+                }
+            }
+            "--help" | "-h" => {
+                println!("{}", usage);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(anyhow!("unknown option {}\n{}", arg, usage))
+            }
+            _ => options.inputs.push(arg),
+        }
+    }
+    if options.inputs.is_empty() {
+        return Err(anyhow!("{}", usage));
+    }
+    if let Some(path) = parameters {
+        options.parameters = read_parameters(&path)?;
+    }
+    Ok(options)
+}
+
+pub fn read_input(path: &str) -> Result<String> {
+    if path == "-" {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content)?;
+        return Ok(content);
+    }
+    fs::read_to_string(path).with_context(|| format!("reading {}", path))
+}
+
+/// Reads the parameters, or a constraint whose parameters are used.
+fn read_parameters(path: &str) -> Result<Parameters> {
+    let mut parameters: Value =
+        serde_yaml::from_str(&read_input(path)?).with_context(|| format!("parsing {}", path))?;
+    // A constraint, take its parameters.
+    if parameters.get("kind").is_some() {
+        parameters = parameters
+            .pointer("/spec/parameters")
+            .cloned()
+            .unwrap_or(Value::Null);
+    }
+    parse_parameters(&parameters.to_string()).map_err(|e| anyhow!("{}: {}", path, e))
+}
+
+/// Exits with 0 when `run` finds no violation, 1 when it does, and 2 when it
+/// fails.
+pub fn exit(run: fn() -> Result<bool>) -> ! {
+    process::exit(match run() {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            2
+        }
+    })
+}
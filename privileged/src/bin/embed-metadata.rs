@@ -0,0 +1,31 @@
+//! Copies a freshly built policy module, appending the policy metadata as a
+//! custom section so it can be read without instantiating the module.
+//!
+//! Usage: `embed-metadata <input.wasm> <policy.wasm>`.
+
+use std::env;
+use std::fs;
+
+use anyhow::{anyhow, Context, Result};
+use policy_sdk::metadata;
+use policy_sdk::Policy;
+use privileged::Privileged;
+
+fn main() -> Result<()> {
+    let args: Vec<_> = env::args().collect();
+    let (input, output) = match (args.get(1), args.get(2)) {
+        (Some(input), Some(output)) => (input, output),
+        _ => return Err(anyhow!("usage: {} <input.wasm> <policy.wasm>", args[0])),
+    };
+
+    let mut wasm = fs::read(input).with_context(|| format!("reading {}", input))?;
+    if metadata::custom_section(&wasm, metadata::CUSTOM_SECTION).is_some() {
+        return Err(anyhow!("{} already embeds the policy metadata", input));
+    }
+    metadata::append_custom_section(
+        &mut wasm,
+        metadata::CUSTOM_SECTION,
+        Privileged::metadata().to_json().as_bytes(),
+    );
+    fs::write(output, wasm).with_context(|| format!("writing {}", output))
+}
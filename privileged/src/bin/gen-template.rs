@@ -0,0 +1,29 @@
+//! Writes the Gatekeeper ConstraintTemplate for the policy, embedding the
+//! given `policy.wasm` and the parameter schema derived from the Rust types.
+//!
+//! Usage: `gen-template <policy.wasm> [template.yaml]`. Without an output
+//! path the template is printed to stdout.
+
+use std::env;
+use std::fs;
+
+use anyhow::{anyhow, Context, Result};
+use privileged::template::ConstraintTemplate;
+
+fn main() -> Result<()> {
+    let args: Vec<_> = env::args().collect();
+    let wasm_path = args
+        .get(1)
+        .ok_or_else(|| anyhow!("usage: {} <policy.wasm> [template.yaml]", args[0]))?;
+
+    let wasm = fs::read(wasm_path).with_context(|| format!("reading {}", wasm_path))?;
+    let template = serde_yaml::to_string(&ConstraintTemplate::new(&wasm))?;
+
+    match args.get(2) {
+        Some(output) => {
+            fs::write(output, template).with_context(|| format!("writing {}", output))?
+        }
+        None => print!("{}", template),
+    }
+    Ok(())
+}
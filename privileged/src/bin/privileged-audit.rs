@@ -0,0 +1,91 @@
+//! Reports the violations of existing objects, like the Gatekeeper audit but
+//! without a live API server, from a dump such as the one of
+//! `kubectl get pods,deploy,ds,sts,job,cronjob -A -o json`.
+//!
+//! Usage: `privileged-audit [--parameters <file>] [--output human|json]
+//! <dump|directory>...`, where `-` reads the dump from stdin. Directories
+//! are read recursively for `.json`, `.yaml` and `.yml` files. The
+//! violations are grouped by namespace and by rule.
+//!
+//! Exits with 1 when there are violations, and with 2 when the input cannot
+//! be read.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use common::{read_input, Output};
+use policy_sdk::Request;
+use privileged::audit::Report;
+
+const USAGE: &str =
+    "usage: privileged-audit [--parameters <file>] [--output human|json] <dump|directory>...";
+
+/// Reads the objects of a dump, or of the dumps in a directory.
+fn read_objects(path: &str, requests: &mut Vec<Request>) -> Result<()> {
+    if path != "-" && Path::new(path).is_dir() {
+        let mut entries = fs::read_dir(path)
+            .with_context(|| format!("reading {}", path))?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<_>>>()?;
+        entries.sort();
+        for entry in entries {
+            let is_manifest = entry
+                .extension()
+                .is_some_and(|ext| ext == "json" || ext == "yaml" || ext == "yml");
+            if entry.is_dir() || is_manifest {
+                read_objects(&entry.display().to_string(), requests)?;
+            }
+        }
+        return Ok(());
+    }
+    let objects =
+        Request::from_documents(&read_input(path)?).with_context(|| format!("parsing {}", path))?;
+    requests.extend(objects);
+    Ok(())
+}
+
+fn print_human(report: &Report) {
+    for (namespace, rules) in &report.namespaces {
+        match namespace.as_str() {
+            "" => println!("(cluster scoped)"),
+            namespace => println!("{}", namespace),
+        }
+        for (rule, violations) in rules {
+            println!("  {}: {} violations", rule, violations.len());
+            for violation in violations {
+                println!(
+                    "    {} {}: {}",
+                    violation.kind, violation.name, violation.message
+                );
+            }
+        }
+    }
+    println!(
+        "{} violations in {} audited objects",
+        report.total_violations, report.audited_objects
+    );
+}
+
+/// Returns whether no violation was found.
+fn run() -> Result<bool> {
+    let options = common::parse_args(USAGE)?;
+
+    let mut requests = Vec::new();
+    for input in &options.inputs {
+        read_objects(input, &mut requests)?;
+    }
+    let report = privileged::audit::audit(&requests, &options.parameters);
+
+    match options.output {
+        Output::Human => print_human(&report),
+        Output::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    Ok(report.total_violations == 0)
+}
+
+fn main() {
+    common::exit(run)
+}
@@ -0,0 +1,124 @@
+//! Evaluates Kubernetes manifests against the policy, e.g. in pre-commit
+//! hooks or in CI against rendered Helm charts.
+//!
+//! Usage: `privileged-eval [--parameters <file>] [--output human|json]
+//! <manifest>...`, where `-` reads the manifest from stdin. A manifest is a
+//! JSON or YAML file with one or more documents, each a Pod, a workload
+//! (Deployment, CronJob, ...), a `List` of them or an `AdmissionReview`.
+//! Other kinds are skipped. The parameters file holds the parameters, or a
+//! constraint whose parameters are used.
+//!
+//! Exits with 1 when an object is denied, and with 2 when the input cannot be
+//! read.
+
+mod common;
+
+use anyhow::{Context, Result};
+use common::{read_input, Output};
+use policy_sdk::{EnforcementAction, Exemption, Policy, Request, Violation};
+use privileged::{workload, Parameters, Privileged};
+use serde::Serialize;
+use serde_json::Value;
+
+const USAGE: &str =
+    "usage: privileged-eval [--parameters <file>] [--output human|json] <manifest>...";
+
+/// Result for one object of a manifest.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectResult {
+    file: String,
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    namespace: Option<String>,
+    name: String,
+    allowed: bool,
+    violations: Vec<Violation>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    exemptions: Vec<Exemption>,
+    /// How the decision was reached, with `explain: true` in the parameters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<Value>,
+}
+
+impl ObjectResult {
+    /// `Kind namespace/name`, or `Kind name` for cluster scoped objects.
+    fn title(&self) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{} {}/{}", self.kind, namespace, self.name),
+            None => format!("{} {}", self.kind, self.name),
+        }
+    }
+}
+
+/// Reads the objects of a manifest, see [`Request::from_documents`].
+fn read_manifest(path: &str) -> Result<Vec<Request>> {
+    Request::from_documents(&read_input(path)?).with_context(|| format!("parsing {}", path))
+}
+
+/// Evaluates the object with the same code as the policy module. Objects
+/// other than pods and workloads are skipped.
+fn evaluate(file: &str, request: &Request, params: &Parameters) -> Option<ObjectResult> {
+    let kind = request.kind()?;
+    workload::pod_spec_pointer(kind)?;
+    let decision = Privileged::validate(request, params);
+    let metadata = &request.object()["metadata"];
+    Some(ObjectResult {
+        file: file.to_string(),
+        kind: kind.to_string(),
+        namespace: metadata["namespace"].as_str().map(str::to_string),
+        name: metadata["name"].as_str().unwrap_or_default().to_string(),
+        allowed: decision.is_allowed(),
+        violations: decision.violations,
+        exemptions: decision.exemptions,
+        trace: decision.trace,
+    })
+}
+
+/// Prints the objects with violations, denying them or not, and the
+/// exempted ones.
+fn print_human(results: &[ObjectResult]) {
+    for result in results
+        .iter()
+        .filter(|r| !r.violations.is_empty() || !r.exemptions.is_empty())
+    {
+        println!("{}: {}", result.file, result.title());
+        for violation in &result.violations {
+            match violation.enforcement_action {
+                EnforcementAction::Deny => println!("  {}", violation.message),
+                EnforcementAction::Warn => println!("  warning: {}", violation.message),
+                EnforcementAction::Dryrun => println!("  dryrun: {}", violation.message),
+            }
+        }
+        for exemption in &result.exemptions {
+            println!("  exempted: {}", exemption.message);
+        }
+    }
+    let denied = results.iter().filter(|r| !r.allowed).count();
+    println!("{} objects checked, {} denied", results.len(), denied);
+}
+
+/// Returns whether all the objects are allowed.
+fn run() -> Result<bool> {
+    let options = common::parse_args(USAGE)?;
+
+    let mut results = Vec::new();
+    for file in &options.inputs {
+        let manifest = read_manifest(file)?;
+        results.extend(
+            manifest
+                .iter()
+                .filter_map(|r| evaluate(file, r, &options.parameters)),
+        );
+    }
+
+    match options.output {
+        Output::Human => print_human(&results),
+        Output::Json => println!("{}", serde_json::to_string_pretty(&results)?),
+    }
+    Ok(results.iter().all(|r| r.allowed))
+}
+
+fn main() {
+    common::exit(run)
+}
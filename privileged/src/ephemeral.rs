@@ -0,0 +1,166 @@
+//! Requests to the `pods/ephemeralcontainers` subresource, sent by
+//! `kubectl debug` to add ephemeral containers to a running pod.
+
+use k8s_openapi::api::core::v1 as apicore;
+use policy_sdk::Request;
+
+use crate::params::image_matches;
+use crate::{workload, Parameters};
+
+/// The subresource ephemeral containers are added through.
+pub const SUBRESOURCE: &str = "ephemeralcontainers";
+
+/// Returns the pod to validate: the ephemeral containers added to the pod,
+/// or `None` when the user may add any. The rest of the pod cannot change
+/// through the subresource and was validated when the pod was created, so
+/// only what the containers inherit is kept: the pod security context, and
+/// the user namespace of the pod. Only the rules about containers run on it,
+/// see [`crate::rules::evaluate_containers`].
+pub fn pod_to_validate(
+    request: &Request,
+    pod: &apicore::PodSpec,
+    params: &Parameters,
+) -> Option<apicore::PodSpec> {
+    let debug = &params.debug_containers;
+    let is_exempt_user = request.user_info().is_some_and(|user| {
+        user.groups
+            .iter()
+            .any(|group| debug.exempt_groups.contains(group))
+    });
+    if is_exempt_user {
+        return None;
+    }
+
+    let old_names: Vec<String> = request
+        .old_object()
+        .and_then(workload::pod_spec)
+        .and_then(Result::ok)
+        .and_then(|old_pod| old_pod.ephemeral_containers)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|container| container.name)
+        .collect();
+    let added = pod
+        .ephemeral_containers
+        .iter()
+        .flatten()
+        .filter(|container| !old_names.contains(&container.name))
+        .filter(|container| !image_matches(&debug.exempt_images, container.image.as_deref()))
+        .cloned()
+        .collect();
+
+    Some(apicore::PodSpec {
+        ephemeral_containers: Some(added),
+        security_context: pod.security_context.clone(),
+        host_users: pod.host_users,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Privileged;
+    use anyhow::Result;
+    use policy_sdk::{Decision, Policy};
+    use serde_json::{json, Value};
+
+    /// A `kubectl debug` of a privileged pod that already had a privileged
+    /// debug container.
+    fn debug_request(container: Value, groups: &[&str]) -> Result<Request> {
+        let privileged = json!({"privileged": true});
+        let old_spec = json!({
+            "containers": [{"name": "app", "image": "app", "securityContext": privileged}],
+            "ephemeralContainers": [
+                {"name": "debugger-1", "image": "busybox", "securityContext": privileged},
+            ],
+        });
+        let mut spec = old_spec.clone();
+        spec["ephemeralContainers"]
+            .as_array_mut()
+            .unwrap()
+            .push(container);
+        Request::from_admission_review(&json!({
+            "kind": "AdmissionReview",
+            "request": {
+                "operation": "UPDATE",
+                "subResource": "ephemeralcontainers",
+                "userInfo": {"username": "alice", "groups": groups},
+                "object": {"kind": "Pod", "metadata": {"name": "app"}, "spec": spec},
+                "oldObject": {"kind": "Pod", "metadata": {"name": "app"}, "spec": old_spec},
+            },
+        }))
+    }
+
+    fn validate_request(request: &Request, params: &Parameters) -> Decision {
+        Privileged::validate(request, params)
+    }
+
+    #[test]
+    fn validate_added_containers_only_test() -> Result<()> {
+        let params = Parameters::default();
+        let request = debug_request(
+            json!({"name": "debugger-2", "image": "busybox", "securityContext": {"privileged": true}}),
+            &[],
+        )?;
+        let violations = validate_request(&request, &params).violations;
+        let messages: Vec<_> = violations.iter().map(|v| v.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec!["Privileged ephemeral container is not allowed: debugger-2"]
+        );
+
+        let request = debug_request(json!({"name": "debugger-2", "image": "busybox"}), &[])?;
+        assert!(
+            validate_request(&request, &params).is_allowed(),
+            "The existing containers must not be validated again"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn exempt_debug_images_and_groups_test() -> Result<()> {
+        let params: Parameters = policy_sdk::parse_parameters(
+            r#"{"debugContainers": {"exemptImages": ["registry.example.com/debug-*"], "exemptGroups": ["oncall"]}}"#,
+        )?;
+        let privileged = json!({"privileged": true});
+
+        let request = debug_request(
+            json!({"name": "debugger-2", "image": "registry.example.com/debug-tools", "securityContext": privileged}),
+            &[],
+        )?;
+        assert!(validate_request(&request, &params).is_allowed());
+
+        let request = debug_request(
+            json!({"name": "debugger-2", "image": "busybox", "securityContext": privileged}),
+            &["system:authenticated", "oncall"],
+        )?;
+        assert!(validate_request(&request, &params).is_allowed());
+
+        let request = debug_request(
+            json!({"name": "debugger-2", "image": "busybox", "securityContext": privileged}),
+            &["system:authenticated"],
+        )?;
+        assert!(!validate_request(&request, &params).is_allowed());
+        Ok(())
+    }
+
+    #[test]
+    fn skip_pod_rules_test() -> Result<()> {
+        let params: Parameters = policy_sdk::parse_parameters(
+            r#"{"rules": {"hostUsers": {"enabled": true}, "hostNamespaces": {"enabled": true}}, "debugContainers": {"exemptImages": ["registry.example.com/debug-*"]}}"#,
+        )?;
+        let request = debug_request(json!({"name": "debugger-2", "image": "busybox"}), &[])?;
+        assert!(
+            validate_request(&request, &params).is_allowed(),
+            "Debugging a pod running with the users of the host must be allowed"
+        );
+
+        let request = debug_request(
+            json!({"name": "debugger-2", "image": "registry.example.com/debug-tools"}),
+            &[],
+        )?;
+        assert!(validate_request(&request, &params).is_allowed());
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+//! Exemptions of containers granted by an annotation of their pod, for pods
+//! that need privileges for a while, e.g. until a ticket is resolved:
+//!
+//! ```yaml
+//! metadata:
+//!   annotations:
+//!     policy.example.com/privileged-exempt: "nginx=OPS-123;expires=2026-12-31"
+//! ```
+//!
+//! The annotation is only read when the `exemptionAnnotation` parameter
+//! names it. Several containers are separated by commas. A container is
+//! exempted from all the rules until the end of the expiry day, in UTC.
+
+use anyhow::{anyhow, Result};
+use policy_sdk::{logging, Exemption};
+use serde_json::Value;
+
+use crate::{workload, Parameters};
+
+/// A container exempted by the annotation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerExemption {
+    pub container: String,
+    /// Why the container is exempted, e.g. a ticket.
+    pub justification: String,
+    /// The last day of the exemption, as `YYYY-MM-DD`.
+    pub expires: String,
+    /// The last day of the exemption, in days since the Unix epoch.
+    expiry_day: i64,
+}
+
+impl ContainerExemption {
+    pub fn is_active(&self, today: i64) -> bool {
+        today <= self.expiry_day
+    }
+
+    /// The exemption as reported in the decision.
+    pub fn to_exemption(&self) -> Exemption {
+        Exemption::new(format!(
+            "Container {} is exempted until {}: {}",
+            self.container, self.expires, self.justification
+        ))
+    }
+}
+
+/// Parses the value of the annotation: comma separated
+/// `<container>=<justification>;expires=<YYYY-MM-DD>` entries.
+pub fn parse(value: &str) -> Result<Vec<ContainerExemption>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(parse_entry)
+        .collect()
+}
+
+fn parse_entry(entry: &str) -> Result<ContainerExemption> {
+    let invalid = || {
+        anyhow!(
+            "invalid exemption {:?}, expected <container>=<justification>;expires=<YYYY-MM-DD>",
+            entry
+        )
+    };
+    let (container, rest) = entry.split_once('=').ok_or_else(invalid)?;
+    let (justification, expires) = rest.split_once(";expires=").ok_or_else(invalid)?;
+    if container.is_empty() || justification.is_empty() {
+        return Err(invalid());
+    }
+    Ok(ContainerExemption {
+        container: container.to_string(),
+        justification: justification.to_string(),
+        expires: expires.to_string(),
+        expiry_day: parse_date(expires).ok_or_else(invalid)?,
+    })
+}
+
+/// Returns the exemptions the annotation of the pod, or of the pod template
+/// of a workload, grants on the given day. Expired exemptions are logged,
+/// as is an invalid annotation, which grants nothing.
+pub fn granted(object: &Value, params: &Parameters, today: i64) -> Vec<ContainerExemption> {
+    let annotation = params.exemption_annotation.as_str();
+    if annotation.is_empty() {
+        return Vec::new();
+    }
+    let value = match workload::pod_metadata(object)
+        .and_then(|metadata| metadata["annotations"][annotation].as_str())
+    {
+        Some(value) => value,
+        None => return Vec::new(),
+    };
+    match parse(value) {
+        Ok(exemptions) => exemptions
+            .into_iter()
+            .filter(|exemption| {
+                let active = exemption.is_active(today);
+                if !active {
+                    logging::info("exemption expired")
+                        .field("container", &exemption.container)
+                        .field("expires", &exemption.expires)
+                        .emit();
+                }
+                active
+            })
+            .collect(),
+        Err(e) => {
+            logging::warn("invalid exemption annotation")
+                .field("annotation", annotation)
+                .field("error", e.to_string())
+                .emit();
+            Vec::new()
+        }
+    }
+}
+
+/// The names of the exempted containers.
+pub fn containers(exemptions: &[ContainerExemption]) -> Vec<String> {
+    exemptions
+        .iter()
+        .map(|exemption| exemption.container.clone())
+        .collect()
+}
+
+/// The current day, in days since the Unix epoch, see [`policy_sdk::now`].
+pub fn today() -> i64 {
+    policy_sdk::now().div_euclid(86400)
+}
+
+/// Returns the days since the Unix epoch of a `YYYY-MM-DD` date.
+fn parse_date(date: &str) -> Option<i64> {
+    let mut parts = date.splitn(3, '-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    let is_number = |part: &str, len| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+    if !is_number(year, 4) || !is_number(month, 2) || !is_number(day, 2) {
+        return None;
+    }
+    let (year, month, day): (i64, i64, i64) =
+        (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    let is_leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if is_leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if !(1..=days_in_month).contains(&day) {
+        return None;
+    }
+
+    // Days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146097 + day_of_era - 719468)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_date_test() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2000-03-01"), Some(11017));
+        assert_eq!(parse_date("2024-02-29"), Some(19782));
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date("2026-13-01"), None);
+        assert_eq!(parse_date("2026-1-01"), None);
+        assert_eq!(parse_date("+026-01-01"), None);
+    }
+
+    #[test]
+    fn parse_annotation_test() -> Result<()> {
+        let exemptions = parse("nginx=OPS-123;expires=2026-12-31, agent=OPS-7;expires=2027-01-15")?;
+        assert_eq!(exemptions.len(), 2);
+        assert_eq!(exemptions[0].container, "nginx");
+        assert_eq!(exemptions[0].justification, "OPS-123");
+        assert_eq!(exemptions[0].expires, "2026-12-31");
+        assert_eq!(
+            exemptions[0].to_exemption(),
+            Exemption::new("Container nginx is exempted until 2026-12-31: OPS-123")
+        );
+        assert_eq!(exemptions[1].container, "agent");
+
+        assert!(parse("nginx=OPS-123").is_err(), "An expiry is required");
+        assert!(parse("nginx=OPS-123;expires=soon").is_err());
+        assert!(parse("=OPS-123;expires=2026-12-31").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn granted_until_expiry_test() {
+        let params = Parameters {
+            exemption_annotation: "policy.example.com/privileged-exempt".to_string(),
+            ..Parameters::default()
+        };
+        let deployment = json!({
+            "kind": "Deployment",
+            "spec": {"template": {
+                "metadata": {"annotations": {
+                    "policy.example.com/privileged-exempt": "nginx=OPS-123;expires=2026-12-31",
+                }},
+                "spec": {"containers": [{"name": "nginx"}]},
+            }},
+        });
+        let last_day = parse_date("2026-12-31").unwrap();
+
+        let granted_on = |params: &Parameters, day| -> Vec<String> {
+            granted(&deployment, params, day)
+                .into_iter()
+                .map(|exemption| exemption.container)
+                .collect()
+        };
+        assert_eq!(granted_on(&params, last_day), vec!["nginx"]);
+        assert!(granted_on(&params, last_day + 1).is_empty());
+        assert!(
+            granted_on(&Parameters::default(), last_day).is_empty(),
+            "The annotation must only be read when enabled"
+        );
+    }
+}
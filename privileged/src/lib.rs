@@ -1,597 +1,721 @@
-extern crate alloc;
-extern crate core;
-extern crate wee_alloc;
-
-use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
 
 use k8s_openapi::api::core::v1 as apicore;
+use policy_sdk::{external_data, logging};
+use policy_sdk::{
+    policy, schema_for, Decision, EnforcementAction, Metadata, Operation, PatchOperation, Policy,
+    Request, Violation,
+};
+use serde_json::Value;
+
+pub mod audit;
+pub mod ephemeral;
+pub mod exemption;
+mod params;
+pub mod pod;
+pub mod rules;
+pub mod template;
+pub mod trace;
+pub mod workload;
+
+pub use params::{DebugContainers, NamespaceLabels, Parameters};
+use trace::Trace;
+
+/// Denies pods running privileged containers, along with the other rules
+/// enabled in the parameters, see [`rules`].
+pub struct Privileged;
+
+#[policy]
+impl Policy for Privileged {
+    type Params = Parameters;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            name: template::NAME,
+            title: template::TITLE,
+            version: env!("CARGO_PKG_VERSION"),
+            constraint_kind: template::KIND,
+            kinds: workload::match_kinds(),
+            parameters: schema_for::<Parameters>(),
+            mutating: true,
+        }
+    }
 
-use alloc::vec::Vec;
-use std::mem::MaybeUninit;
-use std::env;
+    fn validate(request: &Request, params: &Parameters) -> Decision {
+        if let Some(reason) = exemption_reason(request, params) {
+            return skipped(Decision::allow(), reason, params);
+        }
+        let pod_spec = match workload::pod_spec(request.object()) {
+            Some(Ok(pod_spec)) => pod_spec,
+            Some(Err(e)) => {
+                return skipped(Decision::deny(e.to_string()), "invalid pod spec", params)
+            }
+            // Not a pod or a workload creating pods, or there is no pod spec:
+            // there is no data to be validated.
+            None => return skipped(Decision::allow(), "no pod spec", params),
+        };
+
+        let exemptions = exemption::granted(request.object(), params, exemption::today());
+        let exempt_containers = exemption::containers(&exemptions);
+        let namespace = request.namespace();
+        let (pod_spec, results) = if request.subresource() == Some(ephemeral::SUBRESOURCE) {
+            match ephemeral::pod_to_validate(request, &pod_spec, params) {
+                Some(added) => {
+                    let results = rules::evaluate_containers(&added, params, &exempt_containers);
+                    (added, results)
+                }
+                None => {
+                    let reason = "user in debugContainers.exemptGroups";
+                    return skipped(Decision::allow(), reason, params);
+                }
+            }
+        } else {
+            let results = match old_pod_spec(request, params) {
+                Some(old_pod_spec) => evaluate_update(
+                    &pod_spec,
+                    &old_pod_spec,
+                    namespace,
+                    params,
+                    &exempt_containers,
+                ),
+                None => rules::evaluate(&pod_spec, namespace, params, &exempt_containers),
+            };
+            (pod_spec, results)
+        };
+        let mut decision = decide(&results);
+        decision.exemptions = exemptions
+            .iter()
+            .map(exemption::ContainerExemption::to_exemption)
+            .collect();
+        if params.explain {
+            let trace = Trace::new(&pod_spec, &results, &exemptions, decision.is_allowed());
+            decision.trace = Some(trace.to_value());
+        }
+        decision
+    }
 
-/// Logs a message to the console using [`_log`].
-fn log(message: String) {
-    unsafe {
-        let (ptr, len) = string_to_ptr(&message);
-        _log(ptr, len);
+    fn mutate(request: &Request, params: &Parameters) -> Vec<PatchOperation> {
+        if is_exempt(request, params) {
+            return Vec::new();
+        }
+        remediate(request.object(), request.namespace(), params)
     }
 }
 
-#[link(wasm_import_module = "env")]
-extern "C" {
-    /// WebAssembly import which prints a string (linear memory offset,
-    /// byteCount) to the console.
-    ///
-    /// Note: This is not an ownership transfer: Rust still owns the pointer
-    /// and ensures it isn't deallocated during this call.
-    #[link_name = "log"]
-    fn _log(ptr: u32, size: u32);
+/// Returns true if the namespace of the object or the user making the
+/// request is exempted, see `exemptNamespaces`, `exemptUsers`,
+/// `exemptGroups`, `exemptServiceAccounts` and `namespaceLabels`.
+pub fn is_exempt(request: &Request, params: &Parameters) -> bool {
+    exemption_reason(request, params).is_some()
 }
 
-// /// WebAssembly export that accepts a string (linear memory offset, byteCount)
-// /// and calls [`greet`].
-// ///
-// /// Note: The input parameters were returned by [`allocate`]. This is not an
-// /// ownership transfer, so the inputs can be reused after this call.
-// #[cfg_attr(all(target_arch = "wasm32"), export_name = "greet")]
-// #[no_mangle]
-// pub unsafe extern "C" fn _greet(ptr: u32, len: u32) {
-//     greet(&ptr_to_string(ptr, len));
-// }
-
-/// Returns a pointer and size pair for the given string in a way compatible
-/// with WebAssembly numeric types.
-///
-/// Note: This doesn't change the ownership of the String. To intentionally
-/// leak it, use [`std::mem::forget`] on the input after calling this.
-unsafe fn string_to_ptr(s: &String) -> (u32, u32) {
-    return (s.as_ptr() as u32, s.len() as u32);
+/// Returns why the request is exempted, see [`is_exempt`].
+fn exemption_reason(request: &Request, params: &Parameters) -> Option<String> {
+    let namespace = request.namespace();
+    if params.is_exempt_namespace(namespace) {
+        return Some(format!(
+            "namespace {} in exemptNamespaces",
+            namespace.unwrap_or_default()
+        ));
+    }
+    if let Some(user) = request
+        .user_info()
+        .filter(|user| params.is_exempt_user(user))
+    {
+        return Some(format!("user {} exempted", user.username));
+    }
+    namespace
+        .filter(|namespace| has_exempt_labels(request, namespace, &params.namespace_labels))
+        .map(|namespace| format!("namespace {} labelled as in namespaceLabels", namespace))
 }
 
-/// Set the global allocator to the WebAssembly optimized one.
-#[global_allocator]
-static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
-
-/// WebAssembly export that allocates a pointer (linear memory offset) that can
-/// be used for a string.
-///
-/// This is an ownership transfer, which means the caller must call
-/// [`deallocate`] when finished.
-#[cfg_attr(all(target_arch = "wasm32"), export_name = "allocate")]
-#[no_mangle]
-pub extern "C" fn _allocate(size: u32) -> *mut u8 {
-    allocate(size as usize)
+/// Returns the decision taken without running the rules, with its trace
+/// when `explain` is set.
+fn skipped(mut decision: Decision, reason: impl Into<String>, params: &Parameters) -> Decision {
+    if params.explain {
+        decision.trace = Some(Trace::skipped(reason, decision.is_allowed()).to_value());
+    }
+    decision
 }
 
-/// Allocates size bytes and leaks the pointer where they start.
-fn allocate(size: usize) -> *mut u8 {
-    // Allocate the amount of bytes needed.
-    let vec: Vec<MaybeUninit<u8>> = Vec::with_capacity(size);
+/// Returns true if the namespace has all the exempted labels, according to
+/// the external data provider, or else to the inventory. A failed lookup is
+/// logged and exempts nothing.
+fn has_exempt_labels(request: &Request, namespace: &str, params: &NamespaceLabels) -> bool {
+    if params.exempt.is_empty() {
+        return false;
+    }
+    let labels = if !params.provider.is_empty() {
+        external_data::lookup(&params.provider, &[namespace])
+            .and_then(|response| response.get::<BTreeMap<String, String>>(namespace))
+    } else if let Some(inventory) = request.inventory() {
+        inventory
+            .namespace::<apicore::Namespace>(namespace)
+            .map(|namespace| {
+                namespace.map(|namespace| namespace.metadata.labels.unwrap_or_default())
+            })
+    } else {
+        return false;
+    };
+    match labels {
+        Ok(Some(labels)) => params
+            .exempt
+            .iter()
+            .all(|(label, value)| labels.get(label) == Some(value)),
+        Ok(None) => false,
+        Err(e) => {
+            logging::warn("namespace labels lookup failed")
+                .field("namespace", namespace)
+                .field("error", e.to_string())
+                .emit();
+            false
+        }
+    }
+}
 
-    // into_raw leaks the memory to the caller.
-    Box::into_raw(vec.into_boxed_slice()) as *mut u8
+/// The pod spec of the object before the UPDATE, when only new violations
+/// are reported. An old object without a valid pod spec is ignored, all the
+/// violations of the object are then new.
+fn old_pod_spec(request: &Request, params: &Parameters) -> Option<apicore::PodSpec> {
+    if !params.only_new_violations || request.operation() != Some(Operation::Update) {
+        return None;
+    }
+    workload::pod_spec(request.old_object()?)?.ok()
 }
 
-/// WebAssembly export that deallocates a pointer of the given size (linear
-/// memory offset, byteCount) allocated by [`allocate`].
-#[cfg_attr(all(target_arch = "wasm32"), export_name = "deallocate")]
-#[no_mangle]
-pub unsafe extern "C" fn _deallocate(ptr: u32, size: u32) {
-    deallocate(ptr as *mut u8, size as usize);
+/// Runs the enabled rules against the pod of the namespace, except on the
+/// exempted containers, see [`exemption`]. Violations of rules in `dryrun`
+/// mode are logged, and don't affect the decision. Which rule matched which
+/// container is logged at the `debug` level.
+pub fn validate_pod(
+    pod: &apicore::PodSpec,
+    namespace: Option<&str>,
+    params: &Parameters,
+    exempt_containers: &[String],
+) -> Decision {
+    decide(&rules::evaluate(pod, namespace, params, exempt_containers))
 }
 
-/// Retakes the pointer which allows its memory to be freed.
-unsafe fn deallocate(ptr: *mut u8, size: usize) {
-    let _ = Vec::from_raw_parts(ptr, 0, size);
+/// Like [`validate_pod`], but leaves out the violations the pod already had
+/// before the UPDATE, see `onlyNewViolations`. Violations are compared by
+/// rule and message, which name the container, so a container added by the
+/// UPDATE, e.g. an ephemeral container, is reported even if another container
+/// already had the same violation.
+pub fn validate_pod_update(
+    pod: &apicore::PodSpec,
+    old_pod: &apicore::PodSpec,
+    namespace: Option<&str>,
+    params: &Parameters,
+    exempt_containers: &[String],
+) -> Decision {
+    decide(&evaluate_update(
+        pod,
+        old_pod,
+        namespace,
+        params,
+        exempt_containers,
+    ))
 }
 
-fn eval() {
-    let args: Vec<_> = env::args().collect();
-    let object_to_test = &args[1];
-
-    match serde_json::from_str::<apicore::Pod>(object_to_test) {
-        Ok(pod) => {
-            if let Some(pod_spec) = &pod.spec {
-                return match validate_pod(pod_spec) {
-                    Ok(_) => println!("true"),
-                    Err(_) => println!("false"),
-                };
-            };
-            // If there is not pod spec, just accept it. There is no data to be
-            // validated.
-            println!("true")
+/// Runs the enabled rules against the pod, without the violations of the
+/// pod before the UPDATE, see [`validate_pod_update`].
+fn evaluate_update(
+    pod: &apicore::PodSpec,
+    old_pod: &apicore::PodSpec,
+    namespace: Option<&str>,
+    params: &Parameters,
+    exempt_containers: &[String],
+) -> Vec<rules::RuleResult> {
+    let old_results = rules::evaluate(old_pod, namespace, params, exempt_containers);
+    let mut results = rules::evaluate(pod, namespace, params, exempt_containers);
+    for result in &mut results {
+        if let Some(old) = old_results.iter().find(|old| old.rule == result.rule) {
+            result
+                .violations
+                .retain(|finding| !old.violations.contains(finding));
         }
-        Err(_) => println!("false"),
     }
+    results
 }
 
-/// WebAssembly export that accepts a string (linear memory offset, byteCount)
-/// and calls [`eval`].
-///
-/// Note: The input parameters were returned by [`allocate`]. This is not an
-/// ownership transfer, so the inputs can be reused after this call.
-#[cfg_attr(all(target_arch = "wasm32"), export_name = "eval")]
-#[no_mangle]
-pub unsafe extern "C" fn _eval() {
-    eval();
-}
-
-fn validate_pod(pod: &apicore::PodSpec) -> Result<bool> {
-    for container in &pod.containers {
-        let container_valid = validate_container(container);
-        if !container_valid {
-            return Err(anyhow!("Privileged container is not allowed"));
-        }
-    }
-    if let Some(init_containers) = &pod.init_containers {
-        for container in init_containers {
-            let container_valid = validate_container(container);
-            if !container_valid {
-                return Err(anyhow!("Privileged init container is not allowed"));
+fn decide(results: &[rules::RuleResult]) -> Decision {
+    let mut decision = Decision::allow();
+    for result in results {
+        for finding in &result.violations {
+            logging::debug("rule matched")
+                .field("rule", result.rule)
+                .field("container", &finding.container)
+                .field("action", result.action)
+                .emit();
+            if result.action == EnforcementAction::Dryrun {
+                logging::info("dryrun violation")
+                    .field("rule", result.rule)
+                    .field("violation", &finding.message)
+                    .emit();
             }
+            let violation = Violation::new(&finding.message)
+                .with_rule(result.rule)
+                .with_action(result.action)
+                .with_id(result.id)
+                .with_severity(result.severity)
+                .with_remediation(result.remediation);
+            decision.violations.push(match &result.documentation_url {
+                Some(url) => violation.with_documentation_url(url),
+                None => violation,
+            });
         }
     }
-    if let Some(ephemeral_containers) = &pod.ephemeral_containers {
-        for container in ephemeral_containers {
-            let container_valid = validate_ephemeral_container(container);
-            if !container_valid {
-                return Err(anyhow!("Privileged ephemeral container is not allowed"));
-            }
-        }
-    }
-    Ok(true)
+    decision
 }
 
-fn validate_ephemeral_container(container: &apicore::EphemeralContainer) -> bool {
-    if let Some(security_context) = &container.security_context {
-        return !security_context.privileged.unwrap_or(false);
-    }
-    true
-}
-
-fn validate_container(container: &apicore::Container) -> bool {
-    if let Some(security_context) = &container.security_context {
-        return !security_context.privileged.unwrap_or(false);
-    }
-    true
+/// Returns the JSONPatch fixing what the enabled rules can fix in the pod or
+/// workload of the namespace, with the paths of its containers, see
+/// [`rules::remediate`]. Objects without a valid pod spec and exempted
+/// containers are left alone.
+pub fn remediate(
+    object: &Value,
+    namespace: Option<&str>,
+    params: &Parameters,
+) -> Vec<PatchOperation> {
+    let pointer = match object["kind"].as_str().and_then(workload::pod_spec_pointer) {
+        Some(pointer) => pointer,
+        None => return Vec::new(),
+    };
+    let pod_spec = match workload::pod_spec(object) {
+        Some(Ok(pod_spec)) => pod_spec,
+        _ => return Vec::new(),
+    };
+    let exemptions = exemption::granted(object, params, exemption::today());
+    let mut patch = pod::PodPatch::new(object.clone(), pointer);
+    rules::remediate(
+        &pod_spec,
+        namespace,
+        params,
+        &exemption::containers(&exemptions),
+        &mut patch,
+    );
+    patch.into_patch().into_operations()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use anyhow::Result;
+    use policy_sdk::metadata::{custom_section, CUSTOM_SECTION};
+    use policy_sdk::{evaluate, Inventory, Outcome, Severity};
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    const PRIVILEGED_POD: &str = r#"{"apiVersion":"v1","kind":"Pod","metadata":{"name":"nginx"},"spec":{"containers":[{"name":"nginx","image":"nginx","securityContext":{"privileged":true}}]}}"#;
 
     #[test]
-    fn accept_pod_when_all_ephemeral_containers_are_not_privileged_test() -> Result<()> {
-        let result = validate_pod(&apicore::PodSpec {
-            ephemeral_containers: Some(vec![
-                apicore::EphemeralContainer {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::EphemeralContainer::default()
-                },
-                apicore::EphemeralContainer {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::EphemeralContainer::default()
-                },
-                apicore::EphemeralContainer {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::EphemeralContainer::default()
-                },
-            ]),
-            ..apicore::PodSpec::default()
-        });
-        assert!(
-            result.is_ok(),
-            "Pod with no privileged ephemeral container should be accepted by the validator"
+    fn dryrun_violations_do_not_deny_test() {
+        assert_eq!(
+            evaluate::<Privileged>(
+                PRIVILEGED_POD,
+                r#"{"rules":{"privileged":{"enforcementAction":"dryrun"}}}"#
+            ),
+            Outcome::Allowed
         );
-        Ok(())
     }
 
     #[test]
-    fn reject_pod_when_all_ephemeral_container_is_privileged_test() -> Result<()> {
-        let result = validate_pod(&apicore::PodSpec {
-            ephemeral_containers: Some(vec![
-                apicore::EphemeralContainer {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(true),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::EphemeralContainer::default()
-                },
-                apicore::EphemeralContainer {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(true),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::EphemeralContainer::default()
-                },
-                apicore::EphemeralContainer {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(true),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::EphemeralContainer::default()
-                },
-            ]),
-            ..apicore::PodSpec::default()
-        });
-        assert!(
-            result.is_err(),
-            "Pod with all privileged ephemeral container should be rejected by the validator"
+    fn violation_hints_test() -> Result<()> {
+        let params: Parameters = policy_sdk::parse_parameters(
+            r#"{"rules":{"privileged":{"severity":"high","documentationUrl":"https://docs.example.com/privileged"}}}"#,
+        )?;
+        let decision = Privileged::validate(&Request::from_json(PRIVILEGED_POD)?, &params);
+        assert_eq!(
+            serde_json::to_value(&decision.violations)?,
+            serde_json::json!([{
+                "message": "Privileged container is not allowed: nginx",
+                "rule": "privileged",
+                "enforcementAction": "deny",
+                "id": "PSP-PRIV-001",
+                "severity": "high",
+                "remediation": "Remove `privileged: true` from the securityContext of the container.",
+                "documentationUrl": "https://docs.example.com/privileged",
+            }])
         );
-        Ok(())
-    }
 
-    #[test]
-    fn reject_pod_when_one_ephemeral_container_is_privileged_test() -> Result<()> {
-        let result = validate_pod(&apicore::PodSpec {
-            ephemeral_containers: Some(vec![
-                apicore::EphemeralContainer {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::EphemeralContainer::default()
-                },
-                apicore::EphemeralContainer {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::EphemeralContainer::default()
-                },
-                apicore::EphemeralContainer {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(true),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::EphemeralContainer::default()
-                },
-            ]),
-            ..apicore::PodSpec::default()
-        });
-        assert!(result.is_err(),
-            "Pod with only a single privileged ephemeral container should be rejected by the validator"
+        let decision =
+            Privileged::validate(&Request::from_json(PRIVILEGED_POD)?, &Parameters::default());
+        assert_eq!(decision.violations[0].severity, Some(Severity::Critical));
+        assert_eq!(
+            decision.violations[0].documentation_url, None,
+            "There is no documentation unless a parameter links to it"
         );
         Ok(())
     }
 
     #[test]
-    fn accept_pod_when_init_containers_are_not_privileged_test() -> Result<()> {
-        let result = validate_pod(&apicore::PodSpec {
-            init_containers: Some(vec![
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-            ]),
-            ..apicore::PodSpec::default()
-        });
-        assert!(
-            result.is_ok(),
-            "Pod with no privileged init container should be accepted by the validator"
+    fn evaluate_with_default_parameters_test() {
+        assert_eq!(evaluate::<Privileged>(PRIVILEGED_POD, ""), Outcome::Denied);
+        assert_eq!(
+            evaluate::<Privileged>(PRIVILEGED_POD, "{}"),
+            Outcome::Denied
         );
-        Ok(())
     }
 
     #[test]
-    fn reject_pod_when_one_init_container_is_privileged_test() -> Result<()> {
-        let result = validate_pod(&apicore::PodSpec {
-            init_containers: Some(vec![
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(true),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-            ]),
-            ..apicore::PodSpec::default()
-        });
-        assert!(
-            result.is_err(),
-            "Pod with only a single privileged init container should be rejected by the validator"
+    fn evaluate_yaml_documents_test() {
+        let documents = r#"
+apiVersion: v1
+kind: Service
+metadata:
+  name: web
+spec:
+  ports:
+    - port: 80
+---
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: web
+spec:
+  template:
+    spec:
+      containers:
+        - name: nginx
+          image: nginx
+          securityContext:
+            privileged: true
+"#;
+        assert_eq!(
+            evaluate::<Privileged>(documents, ""),
+            Outcome::Denied,
+            "The pod template of the Deployment must be validated"
+        );
+        assert_eq!(
+            evaluate::<Privileged>(
+                &documents.replace("privileged: true", "privileged: false"),
+                ""
+            ),
+            Outcome::Allowed,
+            "Objects without pods must be allowed"
         );
-        Ok(())
     }
 
     #[test]
-    fn reject_pod_when_all_init_containers_are_privileged_test() -> Result<()> {
-        let result = validate_pod(&apicore::PodSpec {
-            init_containers: Some(vec![
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(true),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(true),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(true),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-            ]),
-            ..apicore::PodSpec::default()
-        });
-        assert!(
-            result.is_err(),
-            "Pod with all privileged init containers should be rejected by the validator"
+    fn evaluate_with_exempt_image_test() {
+        assert_eq!(
+            evaluate::<Privileged>(PRIVILEGED_POD, r#"{"exemptImages":["nginx"]}"#),
+            Outcome::Allowed
         );
-        Ok(())
     }
 
     #[test]
-    fn accecpt_pod_when_containers_are_not_privileged_test() -> Result<()> {
-        let result = validate_pod(&apicore::PodSpec {
-            containers: vec![
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-            ],
-            ..apicore::PodSpec::default()
-        });
+    fn evaluate_with_invalid_parameters_test() {
+        let outcome = evaluate::<Privileged>(PRIVILEGED_POD, r#"{"exemptImage":["nginx"]}"#);
         assert!(
-            result.is_ok(),
-            "Pod with no privileged container should be accepted by the validator"
+            matches!(outcome, Outcome::InvalidParameters(_)),
+            "A typo in the parameters must not be mistaken for an allowed request"
         );
-        Ok(())
+        assert!(outcome.to_string().starts_with("error: invalid parameters"));
+    }
+
+    /// An UPDATE of a privileged pod, with `containers` added to the old
+    /// pod's.
+    fn update_review(ephemeral_containers: &str) -> String {
+        format!(
+            r#"{{
+  "kind": "AdmissionReview",
+  "request": {{
+    "operation": "UPDATE",
+    "object": {{"kind": "Pod", "metadata": {{"name": "nginx"}}, "spec": {{
+      "containers": [{{"name": "nginx", "image": "nginx", "securityContext": {{"privileged": true}}}}],
+      "ephemeralContainers": [{}]}}}},
+    "oldObject": {{"kind": "Pod", "metadata": {{"name": "nginx"}}, "spec": {{
+      "containers": [{{"name": "nginx", "image": "nginx", "securityContext": {{"privileged": true}}}}]}}}}
+  }}
+}}"#,
+            ephemeral_containers
+        )
     }
 
     #[test]
-    fn reject_pod_when_one_container_is_privileged_test() -> Result<()> {
-        let result = validate_pod(&apicore::PodSpec {
-            containers: vec![
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(false),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(true),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-            ],
-            ..apicore::PodSpec::default()
-        });
+    fn only_new_violations_test() {
+        let only_new = r#"{"onlyNewViolations":true}"#;
+        let unchanged = update_review("");
+        assert_eq!(
+            evaluate::<Privileged>(&unchanged, only_new),
+            Outcome::Allowed,
+            "Existing violations must be grandfathered"
+        );
+        assert_eq!(
+            evaluate::<Privileged>(&unchanged, "{}"),
+            Outcome::Denied,
+            "All the violations must be reported by default"
+        );
 
-        assert!(
-            result.is_err(),
-            "Pod with only a single privileged container should be rejected by the validator"
+        let debug = update_review(
+            r#"{"name": "debugger", "image": "busybox", "securityContext": {"privileged": true}}"#,
         );
-        Ok(())
+        assert_eq!(
+            evaluate::<Privileged>(&debug, only_new),
+            Outcome::Denied,
+            "A privileged ephemeral container must be a new violation"
+        );
+        let review: Value = serde_json::from_str(&debug).unwrap();
+        let request = Request::from_admission_review(&review).unwrap();
+        let decision =
+            Privileged::validate(&request, &policy_sdk::parse_parameters(only_new).unwrap());
+        let messages: Vec<_> = decision.violations.iter().map(|v| &v.message).collect();
+        assert_eq!(
+            messages,
+            vec!["Privileged ephemeral container is not allowed: debugger"]
+        );
+
+        let debug = update_review(r#"{"name": "debugger", "image": "busybox"}"#);
+        assert_eq!(evaluate::<Privileged>(&debug, only_new), Outcome::Allowed);
     }
 
     #[test]
-    fn reject_pod_when_all_containers_are_privileged_test() -> Result<()> {
-        let result = validate_pod(&apicore::PodSpec {
-            containers: vec![
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(true),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(true),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-                apicore::Container {
-                    security_context: Some(apicore::SecurityContext {
-                        privileged: Some(true),
-                        ..apicore::SecurityContext::default()
-                    }),
-                    ..apicore::Container::default()
-                },
-            ],
-            ..apicore::PodSpec::default()
+    fn remediate_deployment_test() -> Result<()> {
+        let deployment = serde_json::json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": {"name": "web"},
+            "spec": {"template": {"spec": {
+                "hostNetwork": true,
+                "containers": [
+                    {"name": "app", "image": "app"},
+                    {"name": "proxy", "image": "proxy", "securityContext": {
+                        "privileged": true,
+                        "capabilities": {"add": ["NET_ADMIN", "CHOWN"]},
+                    }},
+                ],
+            }}},
         });
-        assert!(
-            result.is_err(),
-            "Pod with all privileged containers should be rejected by the validator"
+        let params = policy_sdk::parse_parameters::<Parameters>(
+            r#"{"rules": {
+                "allowPrivilegeEscalation": {"enabled": true},
+                "capabilities": {"enabled": true, "allowedCapabilities": ["CHOWN"], "requiredDropCapabilities": ["ALL"]},
+                "hostNamespaces": {"enabled": true, "enforcementAction": "dryrun"}
+            }}"#,
+        )?;
+        let containers = "/spec/template/spec/containers";
+        assert_eq!(
+            serde_json::to_value(remediate(&deployment, None, &params))?,
+            serde_json::json!([
+                {"op": "replace", "path": format!("{}/1/securityContext/privileged", containers), "value": false},
+                {"op": "add", "path": format!("{}/0/securityContext", containers), "value": {"allowPrivilegeEscalation": false}},
+                {"op": "add", "path": format!("{}/1/securityContext/allowPrivilegeEscalation", containers), "value": false},
+                {"op": "add", "path": format!("{}/0/securityContext/capabilities", containers), "value": {"drop": ["ALL"]}},
+                {"op": "replace", "path": format!("{}/1/securityContext/capabilities/add", containers), "value": ["CHOWN"]},
+                {"op": "add", "path": format!("{}/1/securityContext/capabilities/drop", containers), "value": ["ALL"]},
+            ]),
+            "hostNetwork must be left alone, as its rule is in dryrun mode"
         );
         Ok(())
     }
 
     #[test]
-    fn accept_container_is_not_privileged_test() -> Result<()> {
+    fn remediate_host_users_in_untrusted_namespaces_test() -> Result<()> {
+        let pod = serde_json::json!({
+            "kind": "Pod",
+            "metadata": {"name": "web"},
+            "spec": {"containers": [{"name": "app", "image": "app"}]},
+        });
+        let params = policy_sdk::parse_parameters::<Parameters>(
+            r#"{"rules": {"privileged": {"enabled": false}, "hostUsers": {"enabled": true, "namespaces": ["tenant-*"]}}}"#,
+        )?;
         assert_eq!(
-            validate_container(&apicore::Container {
-                security_context: Some(apicore::SecurityContext {
-                    privileged: Some(false),
-                    ..apicore::SecurityContext::default()
-                }),
-                ..apicore::Container::default()
-            }),
-            true,
-            "Non privileged container should be accepted by the validator"
+            serde_json::to_value(remediate(&pod, Some("tenant-a"), &params))?,
+            serde_json::json!([{"op": "add", "path": "/spec/hostUsers", "value": false}])
         );
+        assert!(remediate(&pod, Some("kube-system"), &params).is_empty());
         Ok(())
     }
 
     #[test]
-    fn accept_container_with_no_security_context() -> Result<()> {
+    fn exempt_namespaces_and_users_test() {
+        let daemon_set = r#"{"kind":"DaemonSet","metadata":{"name":"agent","namespace":"kube-system"},"spec":{"template":{"spec":{"containers":[{"name":"agent","image":"agent","securityContext":{"privileged":true}}]}}}}"#;
+        let exempt_namespaces = r#"{"exemptNamespaces":["kube-*"]}"#;
         assert_eq!(
-            validate_container(&apicore::Container {
-                ..apicore::Container::default()
-            }),
-            true,
-            "Non privileged container should be accepted by the validator"
+            evaluate::<Privileged>(daemon_set, exempt_namespaces),
+            Outcome::Allowed
         );
-        Ok(())
-    }
+        assert_eq!(evaluate::<Privileged>(daemon_set, ""), Outcome::Denied);
 
-    #[test]
-    fn reject_privileged_container_test() -> Result<()> {
+        // The pods of the DaemonSet, whose namespace is only in the request.
+        let review = format!(
+            r#"{{"kind":"AdmissionReview","request":{{"operation":"CREATE","namespace":"kube-system","userInfo":{{"username":"system:serviceaccount:kube-system:daemon-set-controller"}},"object":{}}}}}"#,
+            PRIVILEGED_POD
+        );
         assert_eq!(
-            validate_container(&apicore::Container {
-                security_context: Some(apicore::SecurityContext {
-                    privileged: Some(true),
-                    ..apicore::SecurityContext::default()
-                }),
-                ..apicore::Container::default()
-            }),
-            false,
-            "Privileged container should be rejected by the validator"
+            evaluate::<Privileged>(&review, exempt_namespaces),
+            Outcome::Allowed
+        );
+        assert_eq!(
+            evaluate::<Privileged>(
+                &review,
+                r#"{"exemptServiceAccounts":["kube-system/daemon-set-controller"]}"#
+            ),
+            Outcome::Allowed
+        );
+        assert_eq!(
+            evaluate::<Privileged>(&review, r#"{"exemptGroups":["system:masters"]}"#),
+            Outcome::Denied
         );
-        Ok(())
     }
 
     #[test]
-    fn accept_privileged_container_when_privileged_is_none_test() -> Result<()> {
-        assert_eq!(
-            validate_container(&apicore::Container {
-                security_context: Some(apicore::SecurityContext {
-                    privileged: None,
-                    ..apicore::SecurityContext::default()
-                }),
-                ..apicore::Container::default()
-            }),
-            true,
-            "Privileged container should be accepted by the validator when there is no 'privileged' configuration. The default behaviour is disable privileged containers"
-        );
+    fn exempt_namespace_labels_from_inventory_test() -> Result<()> {
+        let params: Parameters = policy_sdk::parse_parameters(
+            r#"{"namespaceLabels":{"exempt":{"privileged-allowed":"true"}}}"#,
+        )?;
+        let inventory = Inventory::parse(
+            r#"{"apiVersion":"v1","kind":"Namespace","metadata":{"name":"ci","labels":{"privileged-allowed":"true"}}}"#,
+        )?;
+        let pod = |namespace: &str| {
+            PRIVILEGED_POD.replace(
+                r#""metadata":{"name":"nginx""#,
+                &format!(r#""metadata":{{"name":"nginx","namespace":"{}""#, namespace),
+            )
+        };
+
+        let request = Request::from_json(&pod("ci"))?;
+        assert!(!is_exempt(&request, &params));
+        let request = request.with_inventory(Arc::new(inventory.clone()));
+        assert!(is_exempt(&request, &params));
+        let request = Request::from_json(&pod("prod"))?.with_inventory(Arc::new(inventory));
+        assert!(!is_exempt(&request, &params));
         Ok(())
     }
 
     #[test]
-    fn accept_ephemeral_container_is_not_privileged_test() -> Result<()> {
+    fn explain_test() -> Result<()> {
+        let pod = r#"{"kind":"Pod","metadata":{"name":"nginx","namespace":"prod"},"spec":{"securityContext":{"runAsUser":1000},"containers":[{"name":"nginx","image":"nginx","securityContext":{"privileged":true}},{"name":"debug","image":"busybox"}]}}"#;
+        let params: Parameters = policy_sdk::parse_parameters(
+            r#"{"explain":true,"rules":{"privileged":{"exemptImages":["busybox"]}}}"#,
+        )?;
+
+        let decision = Privileged::validate(&Request::from_json(pod)?, &params);
         assert_eq!(
-            validate_ephemeral_container(&apicore::EphemeralContainer {
-                security_context: Some(apicore::SecurityContext {
-                    privileged: Some(false),
-                    ..apicore::SecurityContext::default()
-                }),
-                ..apicore::EphemeralContainer::default()
-            }),
-            true,
-            "Non privileged container should be accepted by the validator"
+            decision.trace,
+            Some(serde_json::json!({
+                "containers": [
+                    {
+                        "name": "nginx",
+                        "kind": "container",
+                        "image": "nginx",
+                        "effectiveSecurityContext": {"privileged": true, "runAsUser": 1000},
+                    },
+                    {
+                        "name": "debug",
+                        "kind": "container",
+                        "image": "busybox",
+                        "effectiveSecurityContext": {"runAsUser": 1000},
+                    },
+                ],
+                "rules": [{
+                    "rule": "privileged",
+                    "id": "PSP-PRIV-001",
+                    "action": "deny",
+                    "severity": "critical",
+                    "violations": [{
+                        "container": "nginx",
+                        "message": "Privileged container is not allowed: nginx",
+                    }],
+                    "exemptContainers": ["debug"],
+                }],
+                "outcome": "denied",
+            }))
+        );
+
+        let params: Parameters =
+            policy_sdk::parse_parameters(r#"{"explain":true,"exemptNamespaces":["prod"]}"#)?;
+        let decision = Privileged::validate(&Request::from_json(pod)?, &params);
+        assert_eq!(
+            decision.trace,
+            Some(serde_json::json!({
+                "skipped": "namespace prod in exemptNamespaces",
+                "containers": [],
+                "rules": [],
+                "outcome": "allowed",
+            }))
+        );
+
+        let decision = Privileged::validate(&Request::from_json(pod)?, &Parameters::default());
+        assert_eq!(
+            decision.trace, None,
+            "Decisions must only be explained when asked"
         );
         Ok(())
     }
 
     #[test]
-    fn accept_ephemeral_container_with_no_security_context() -> Result<()> {
+    fn exemption_annotation_test() -> Result<()> {
+        let pod = |expires: &str| {
+            format!(
+                r#"{{"kind":"Pod","metadata":{{"name":"nginx","annotations":{{"example.com/exempt":"nginx=OPS-123;expires={}"}}}},"spec":{{"containers":[{{"name":"nginx","image":"nginx","securityContext":{{"privileged":true}}}}]}}}}"#,
+                expires
+            )
+        };
+        let params: Parameters =
+            policy_sdk::parse_parameters(r#"{"exemptionAnnotation":"example.com/exempt"}"#)?;
+
+        let request = Request::from_json(&pod("2999-12-31"))?;
+        let decision = Privileged::validate(&request, &params);
+        assert!(decision.is_allowed());
         assert_eq!(
-            validate_ephemeral_container(&apicore::EphemeralContainer {
-                ..apicore::EphemeralContainer::default()
-            }),
-            true,
-            "Non privileged container should be accepted by the validator"
+            decision.exemptions,
+            vec![policy_sdk::Exemption::new(
+                "Container nginx is exempted until 2999-12-31: OPS-123"
+            )]
+        );
+        assert!(
+            Privileged::mutate(&request, &params).is_empty(),
+            "Exempted containers must not be patched"
+        );
+
+        let expired = Request::from_json(&pod("2000-01-01"))?;
+        let decision = Privileged::validate(&expired, &params);
+        assert!(!decision.is_allowed());
+        assert!(decision.exemptions.is_empty());
+
+        assert!(
+            !Privileged::validate(&request, &Parameters::default()).is_allowed(),
+            "The annotation must be ignored unless enabled"
         );
         Ok(())
     }
 
     #[test]
-    fn reject_privileged_ephemeral_container_test() -> Result<()> {
-        assert_eq!(
-            validate_ephemeral_container(&apicore::EphemeralContainer {
-                security_context: Some(apicore::SecurityContext {
-                    privileged: Some(true),
-                    ..apicore::SecurityContext::default()
-                }),
-                ..apicore::EphemeralContainer::default()
-            }),
-            false,
-            "Privileged container should be rejected by the validator"
+    fn remediated_pod_is_allowed_test() -> Result<()> {
+        let pod = serde_json::json!({
+            "kind": "Pod",
+            "spec": {
+                "hostPID": true,
+                "initContainers": [{"name": "init", "securityContext": {"privileged": true}}],
+                "containers": [{"name": "app"}],
+            },
+        });
+        let params = policy_sdk::parse_parameters::<Parameters>(
+            r#"{"rules": {"hostNamespaces": {"enabled": true}, "runAsNonRoot": {"enabled": true}}}"#,
+        )?;
+        let mut patch = pod::PodPatch::new(pod.clone(), "/spec");
+        let pod_spec = workload::pod_spec(&pod).unwrap()?;
+        assert!(!validate_pod(&pod_spec, None, &params, &[]).is_allowed());
+
+        rules::remediate(&pod_spec, None, &params, &[], &mut patch);
+        let patched = workload::pod_spec(patch.into_patch().object()).unwrap()?;
+        assert!(
+            validate_pod(&patched, None, &params, &[]).is_allowed(),
+            "The remediation must fix the violations"
         );
         Ok(())
     }
 
     #[test]
-    fn accept_privileged_ephemeral_container_when_privileged_is_none_test() -> Result<()> {
+    fn checked_in_policy_wasm_embeds_metadata_test() -> Result<()> {
+        let wasm = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("policy.wasm"))?;
         assert_eq!(
-            validate_ephemeral_container(&apicore::EphemeralContainer {
-                security_context: Some(apicore::SecurityContext {
-                    privileged: None,
-                    ..apicore::SecurityContext::default()
-                }),
-                ..apicore::EphemeralContainer::default()
-            }),
-            true,
-            "Privileged container should be accepted by the validator when there is no 'privileged' configuration. The default behaviour is disable privileged containers"
+            custom_section(&wasm, CUSTOM_SECTION),
+            Some(Privileged::metadata().to_json().as_bytes()),
+            "policy.wasm is out of date, rebuild it with `make`"
         );
         Ok(())
     }
@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+
+use policy_sdk::UserInfo;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::rules::{
+    allow_privilege_escalation, capabilities, host_namespaces, host_users, privileged,
+    run_as_non_root,
+};
+
+/// Parameters of the policy, mirroring the `parameters` of a
+/// `K8sPSPPrivilegedContainer` constraint.
+///
+/// Unknown fields are rejected so that a typo in a constraint surfaces as an
+/// error instead of silently falling back to the defaults.
+///
+/// The doc comments of the fields end up as descriptions in the generated
+/// ConstraintTemplate.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields, default)]
+pub struct Parameters {
+    /// Any container that uses an image that matches an entry in this list
+    /// will be excluded from enforcement. Prefix-matching can be signified
+    /// with `*`. For example: `my-image-*`. It is recommended that users use
+    /// the fully-qualified Docker image name (e.g. start with a domain name)
+    /// in order to avoid unexpectedly exempting images from an untrusted
+    /// repository.
+    pub exempt_images: Vec<String>,
+    /// Namespaces whose pods and workloads are excluded from enforcement,
+    /// e.g. `kube-system`. `*` matches any characters, as in `kube-*`.
+    pub exempt_namespaces: Vec<String>,
+    /// Users whose requests are excluded from enforcement, by username.
+    /// Requires admission reviews as input.
+    pub exempt_users: Vec<String>,
+    /// Groups whose members' requests are excluded from enforcement.
+    /// Requires admission reviews as input.
+    pub exempt_groups: Vec<String>,
+    /// Service accounts whose requests are excluded from enforcement, as
+    /// `namespace/name`. `*` matches any characters, as in `kube-system/*`.
+    /// Requires admission reviews as input.
+    pub exempt_service_accounts: Vec<String>,
+    /// Exempts the namespaces by their labels, as given by an external data
+    /// provider of the host or by the inventory.
+    pub namespace_labels: NamespaceLabels,
+    /// Name of a pod annotation exempting containers until a given date,
+    /// e.g. `policy.example.com/privileged-exempt` holding
+    /// `nginx=OPS-123;expires=2026-12-31`. Workloads carry it in their pod
+    /// template. Exemptions by annotation are disabled unless this is set.
+    pub exemption_annotation: String,
+    /// On UPDATE, only report the violations the old object did not have, so
+    /// that existing pods and workloads are grandfathered while changes
+    /// introducing new violations are still denied. Applies to admission
+    /// reviews, whose `oldObject` is compared with the `object`.
+    pub only_new_violations: bool,
+    /// Exemptions for the ephemeral containers added by `kubectl debug`
+    /// through the `pods/ephemeralcontainers` subresource. Only the added
+    /// containers are validated on such requests.
+    pub debug_containers: DebugContainers,
+    /// Returns how every decision was reached in its `trace`: the rules
+    /// evaluated, the containers visited with their effective security
+    /// context, and the outcome. Meant to investigate disputed decisions,
+    /// as it makes the result of `validate` much larger.
+    pub explain: bool,
+    /// The rules to run and their settings. Only `privileged` runs unless
+    /// other rules are enabled here.
+    pub rules: Rules,
+}
+
+/// Exemption of namespaces by their labels, e.g. the namespaces labelled
+/// `pod-security.kubernetes.io/enforce: privileged`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields, default)]
+pub struct NamespaceLabels {
+    /// The external data provider returning the labels of a namespace, keyed
+    /// by the name of the namespace. Without a provider, the labels are read
+    /// from the Namespace objects of the inventory the host passes along
+    /// with the review.
+    pub provider: String,
+    /// Namespaces with all of these labels are exempted.
+    pub exempt: BTreeMap<String, String>,
+}
+
+/// Exemptions for the ephemeral containers added to running pods.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields, default)]
+pub struct DebugContainers {
+    /// Debug images exempted from enforcement, in the format of
+    /// `exemptImages`.
+    pub exempt_images: Vec<String>,
+    /// Users in any of these groups may add any ephemeral container, e.g.
+    /// `system:masters` or an on-call group.
+    pub exempt_groups: Vec<String>,
+}
+
+/// Settings of the rules bundled in the module, by rule.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields, default)]
+pub struct Rules {
+    pub privileged: privileged::Params,
+    pub allow_privilege_escalation: allow_privilege_escalation::Params,
+    pub capabilities: capabilities::Params,
+    pub host_namespaces: host_namespaces::Params,
+    pub run_as_non_root: run_as_non_root::Params,
+    pub host_users: host_users::Params,
+}
+
+impl Parameters {
+    /// Returns true if the given image matches one of the `exemptImages`.
+    pub fn is_exempt_image(&self, image: Option<&str>) -> bool {
+        image_matches(&self.exempt_images, image)
+    }
+
+    /// Returns true if the namespace matches one of the `exemptNamespaces`.
+    pub fn is_exempt_namespace(&self, namespace: Option<&str>) -> bool {
+        namespace.is_some_and(|namespace| {
+            self.exempt_namespaces
+                .iter()
+                .any(|pattern| glob_matches(pattern, namespace))
+        })
+    }
+
+    /// Returns true if the user is one of the `exemptUsers`, is in one of
+    /// the `exemptGroups`, or is one of the `exemptServiceAccounts`.
+    pub fn is_exempt_user(&self, user: &UserInfo) -> bool {
+        if self.exempt_users.contains(&user.username)
+            || user.groups.iter().any(|g| self.exempt_groups.contains(g))
+        {
+            return true;
+        }
+        let service_account = user
+            .username
+            .strip_prefix("system:serviceaccount:")
+            .and_then(|account| account.split_once(':'));
+        service_account.is_some_and(|(namespace, name)| {
+            let account = format!("{}/{}", namespace, name);
+            self.exempt_service_accounts
+                .iter()
+                .any(|pattern| glob_matches(pattern, &account))
+        })
+    }
+}
+
+/// Returns true if the value matches the pattern, where `*` matches any
+/// sequence of characters.
+pub fn glob_matches(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match value.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<&str> = parts.collect();
+    let (last, middle) = match parts.split_last() {
+        Some(split) => split,
+        // No `*` in the pattern.
+        None => return rest.is_empty(),
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Returns true if the given image matches one of the patterns, in the format
+/// of `exemptImages`.
+pub fn image_matches(patterns: &[String], image: Option<&str>) -> bool {
+    let image = match image {
+        Some(image) => image,
+        None => return false,
+    };
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => image.starts_with(prefix),
+            None => image == pattern,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use policy_sdk::{parse_parameters, EnforcementAction};
+
+    #[test]
+    fn empty_parameters_use_defaults_test() -> Result<()> {
+        assert_eq!(parse_parameters::<Parameters>("")?, Parameters::default());
+        assert_eq!(
+            parse_parameters::<Parameters>("null")?,
+            Parameters::default()
+        );
+        assert_eq!(parse_parameters::<Parameters>("{}")?, Parameters::default());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_exempt_images_test() -> Result<()> {
+        let params =
+            parse_parameters::<Parameters>(r#"{"exemptImages":["nginx","docker.io/debug-*"]}"#)?;
+        assert_eq!(params.exempt_images, vec!["nginx", "docker.io/debug-*"]);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unknown_parameter_test() {
+        let result = parse_parameters::<Parameters>(r#"{"exemptImage":["nginx"]}"#);
+        assert!(
+            result.is_err(),
+            "A misspelled parameter must be reported instead of being ignored"
+        );
+    }
+
+    #[test]
+    fn reject_mistyped_parameter_test() {
+        let result = parse_parameters::<Parameters>(r#"{"exemptImages":"nginx"}"#);
+        assert!(result.is_err(), "exemptImages must be a list of strings");
+    }
+
+    #[test]
+    fn parse_rules_test() -> Result<()> {
+        let params = parse_parameters::<Parameters>(
+            r#"{"rules":{"capabilities":{"enabled":true,"enforcementAction":"dryrun","requiredDropCapabilities":["ALL"]}}}"#,
+        )?;
+        assert!(params.rules.privileged.enabled);
+        assert!(params.rules.capabilities.enabled);
+        assert_eq!(
+            params.rules.capabilities.enforcement_action,
+            EnforcementAction::Dryrun
+        );
+        assert_eq!(
+            params.rules.capabilities.required_drop_capabilities,
+            vec!["ALL"]
+        );
+        assert!(!params.rules.host_namespaces.enabled);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unknown_rule_test() {
+        let result = parse_parameters::<Parameters>(r#"{"rules":{"hostNamespace":{}}}"#);
+        assert!(result.is_err(), "A misspelled rule must not be ignored");
+    }
+
+    #[test]
+    fn reject_unknown_enforcement_action_test() {
+        let result = parse_parameters::<Parameters>(
+            r#"{"rules":{"privileged":{"enforcementAction":"block"}}}"#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn reject_exempt_images_of_pod_rules_test() {
+        for rule in ["hostNamespaces", "hostUsers"] {
+            let result = parse_parameters::<Parameters>(&format!(
+                r#"{{"rules":{{"{rule}":{{"exemptImages":["nginx"]}}}}}}"#
+            ));
+            assert!(
+                result.is_err(),
+                "{rule} checks the whole pod, images can't be exempted"
+            );
+        }
+    }
+
+    #[test]
+    fn exempt_image_matching_test() {
+        let params = Parameters {
+            exempt_images: vec!["nginx".to_string(), "docker.io/debug-*".to_string()],
+            ..Parameters::default()
+        };
+        assert!(params.is_exempt_image(Some("nginx")));
+        assert!(params.is_exempt_image(Some("docker.io/debug-tools:1.0")));
+        assert!(!params.is_exempt_image(Some("nginx:latest")));
+        assert!(!params.is_exempt_image(Some("docker.io/busybox")));
+        assert!(!params.is_exempt_image(None));
+    }
+
+    #[test]
+    fn glob_matching_test() {
+        assert!(glob_matches("kube-system", "kube-system"));
+        assert!(!glob_matches("kube-system", "kube-system-2"));
+        assert!(glob_matches("kube-*", "kube-public"));
+        assert!(glob_matches("*-system", "gatekeeper-system"));
+        assert!(glob_matches("team-*-ci", "team-a-ci"));
+        assert!(!glob_matches("team-*-ci", "team-a-cd"));
+        assert!(!glob_matches("a*a", "a"), "Parts must not overlap");
+        assert!(glob_matches("*", ""));
+    }
+
+    #[test]
+    fn exempt_user_matching_test() -> Result<()> {
+        let params = parse_parameters::<Parameters>(
+            r#"{"exemptUsers": ["admin"], "exemptGroups": ["system:masters"], "exemptServiceAccounts": ["kube-system/*", "ci/deployer"]}"#,
+        )?;
+        let user = |username: &str, groups: &[&str]| UserInfo {
+            username: username.to_string(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+        };
+        assert!(params.is_exempt_user(&user("admin", &[])));
+        assert!(params.is_exempt_user(&user("alice", &["dev", "system:masters"])));
+        assert!(params.is_exempt_user(&user(
+            "system:serviceaccount:kube-system:daemon-set-controller",
+            &[]
+        )));
+        assert!(params.is_exempt_user(&user("system:serviceaccount:ci:deployer", &[])));
+        assert!(!params.is_exempt_user(&user("system:serviceaccount:ci:builder", &[])));
+        assert!(!params.is_exempt_user(&user("alice", &["dev"])));
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+use std::fmt;
+
+use k8s_openapi::api::core::v1 as apicore;
+use policy_sdk::Patch;
+use serde_json::Value;
+
+/// The lists of containers a pod spec holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerKind {
+    Container,
+    InitContainer,
+    EphemeralContainer,
+}
+
+impl ContainerKind {
+    /// Name of the list in the pod spec.
+    pub fn field(&self) -> &'static str {
+        match self {
+            ContainerKind::Container => "containers",
+            ContainerKind::InitContainer => "initContainers",
+            ContainerKind::EphemeralContainer => "ephemeralContainers",
+        }
+    }
+}
+
+impl fmt::Display for ContainerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContainerKind::Container => write!(f, "container"),
+            ContainerKind::InitContainer => write!(f, "init container"),
+            ContainerKind::EphemeralContainer => write!(f, "ephemeral container"),
+        }
+    }
+}
+
+/// The parts of a container, init container or ephemeral container the rules
+/// look at. Regular and ephemeral containers are distinct types in the API,
+/// this lets the rules handle them the same way.
+#[derive(Debug, Clone, Copy)]
+pub struct ContainerView<'a> {
+    pub kind: ContainerKind,
+    /// Position of the container in its list.
+    pub index: usize,
+    pub name: &'a str,
+    pub image: Option<&'a str>,
+    pub security_context: Option<&'a apicore::SecurityContext>,
+}
+
+impl<'a> ContainerView<'a> {
+    pub fn from_container(
+        kind: ContainerKind,
+        index: usize,
+        container: &'a apicore::Container,
+    ) -> Self {
+        ContainerView {
+            kind,
+            index,
+            name: &container.name,
+            image: container.image.as_deref(),
+            security_context: container.security_context.as_ref(),
+        }
+    }
+
+    pub fn from_ephemeral_container(
+        index: usize,
+        container: &'a apicore::EphemeralContainer,
+    ) -> Self {
+        ContainerView {
+            kind: ContainerKind::EphemeralContainer,
+            index,
+            name: &container.name,
+            image: container.image.as_deref(),
+            security_context: container.security_context.as_ref(),
+        }
+    }
+
+    /// The security context the container runs with: its own, completed by
+    /// the settings of the pod security context that apply to containers.
+    pub fn effective_security_context(&self, pod: &apicore::PodSpec) -> apicore::SecurityContext {
+        let mut security_context = self.security_context.cloned().unwrap_or_default();
+        if let Some(pod_security_context) = &pod.security_context {
+            let pod_security_context = pod_security_context.clone();
+            security_context.run_as_user = security_context
+                .run_as_user
+                .or(pod_security_context.run_as_user);
+            security_context.run_as_group = security_context
+                .run_as_group
+                .or(pod_security_context.run_as_group);
+            security_context.run_as_non_root = security_context
+                .run_as_non_root
+                .or(pod_security_context.run_as_non_root);
+            security_context.se_linux_options = security_context
+                .se_linux_options
+                .or(pod_security_context.se_linux_options);
+            security_context.seccomp_profile = security_context
+                .seccomp_profile
+                .or(pod_security_context.seccomp_profile);
+            security_context.windows_options = security_context
+                .windows_options
+                .or(pod_security_context.windows_options);
+            security_context.app_armor_profile = security_context
+                .app_armor_profile
+                .or(pod_security_context.app_armor_profile);
+        }
+        security_context
+    }
+}
+
+/// Returns true if the pod runs in its own user namespace, with
+/// `hostUsers: false`: its users, root included, then map to unprivileged
+/// users of the host, and its capabilities only apply to the resources of
+/// the namespace.
+pub fn has_user_namespace(pod: &apicore::PodSpec) -> bool {
+    pod.host_users == Some(false)
+}
+
+/// Iterates over all containers of the pod: containers, init containers and
+/// ephemeral containers, in that order.
+pub fn containers(pod: &apicore::PodSpec) -> impl Iterator<Item = ContainerView<'_>> {
+    let containers = pod
+        .containers
+        .iter()
+        .enumerate()
+        .map(|(i, c)| ContainerView::from_container(ContainerKind::Container, i, c));
+    let init_containers = pod
+        .init_containers
+        .iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, c)| ContainerView::from_container(ContainerKind::InitContainer, i, c));
+    let ephemeral_containers = pod
+        .ephemeral_containers
+        .iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, c)| ContainerView::from_ephemeral_container(i, c));
+    containers
+        .chain(init_containers)
+        .chain(ephemeral_containers)
+}
+
+/// A JSONPatch for the object holding a pod spec, addressing the fields of
+/// the pod and of its containers.
+pub struct PodPatch {
+    patch: Patch,
+    /// JSON pointer of the pod spec in the object, see
+    /// [`crate::workload::pod_spec_pointer`].
+    pod_spec: &'static str,
+}
+
+impl PodPatch {
+    pub fn new(object: Value, pod_spec: &'static str) -> Self {
+        PodPatch {
+            patch: Patch::new(object),
+            pod_spec,
+        }
+    }
+
+    /// Sets a field of the pod spec, e.g. `hostPID`.
+    pub fn set_pod(&mut self, field: &str, value: Value) {
+        self.patch
+            .set(&format!("{}/{}", self.pod_spec, field), value);
+    }
+
+    /// Sets a field of the container, e.g. `securityContext/privileged`.
+    pub fn set_container(&mut self, container: &ContainerView, field: &str, value: Value) {
+        self.patch.set(
+            &format!(
+                "{}/{}/{}/{}",
+                self.pod_spec,
+                container.kind.field(),
+                container.index,
+                field
+            ),
+            value,
+        );
+    }
+
+    pub fn into_patch(self) -> Patch {
+        self.patch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effective_security_context_test() {
+        let pod = apicore::PodSpec {
+            security_context: Some(apicore::PodSecurityContext {
+                run_as_user: Some(1000),
+                run_as_non_root: Some(true),
+                app_armor_profile: Some(apicore::AppArmorProfile {
+                    type_: "RuntimeDefault".to_string(),
+                    ..apicore::AppArmorProfile::default()
+                }),
+                ..apicore::PodSecurityContext::default()
+            }),
+            containers: vec![apicore::Container {
+                name: "app".to_string(),
+                security_context: Some(apicore::SecurityContext {
+                    run_as_user: Some(0),
+                    privileged: Some(true),
+                    ..apicore::SecurityContext::default()
+                }),
+                ..apicore::Container::default()
+            }],
+            ..apicore::PodSpec::default()
+        };
+        let container = containers(&pod).next().unwrap();
+        assert_eq!(
+            container.effective_security_context(&pod),
+            apicore::SecurityContext {
+                run_as_user: Some(0),
+                run_as_non_root: Some(true),
+                privileged: Some(true),
+                app_armor_profile: Some(apicore::AppArmorProfile {
+                    type_: "RuntimeDefault".to_string(),
+                    ..apicore::AppArmorProfile::default()
+                }),
+                ..apicore::SecurityContext::default()
+            },
+            "The settings of the container must win over the ones of the pod"
+        );
+    }
+
+    #[test]
+    fn iterate_all_containers_test() {
+        let pod = apicore::PodSpec {
+            containers: vec![apicore::Container {
+                name: "app".to_string(),
+                ..apicore::Container::default()
+            }],
+            init_containers: Some(vec![apicore::Container {
+                name: "init".to_string(),
+                ..apicore::Container::default()
+            }]),
+            ephemeral_containers: Some(vec![
+                apicore::EphemeralContainer {
+                    name: "debug".to_string(),
+                    ..apicore::EphemeralContainer::default()
+                },
+                apicore::EphemeralContainer {
+                    name: "debug2".to_string(),
+                    ..apicore::EphemeralContainer::default()
+                },
+            ]),
+            ..apicore::PodSpec::default()
+        };
+        let visited: Vec<_> = containers(&pod)
+            .map(|c| (c.kind.field(), c.index, c.name))
+            .collect();
+        assert_eq!(
+            visited,
+            vec![
+                ("containers", 0, "app"),
+                ("initContainers", 0, "init"),
+                ("ephemeralContainers", 0, "debug"),
+                ("ephemeralContainers", 1, "debug2"),
+            ]
+        );
+    }
+}
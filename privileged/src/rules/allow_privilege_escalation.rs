@@ -0,0 +1,114 @@
+use k8s_openapi::api::core::v1 as apicore;
+use serde_json::json;
+
+use super::{rule_params, Exemptions, Finding, Rule};
+use crate::pod::{ContainerView, PodPatch};
+
+/// Key of the rule under the `rules` parameter.
+pub const NAME: &str = "allowPrivilegeEscalation";
+
+/// Reported with the violations of the rule.
+pub const RULE: Rule = Rule {
+    name: NAME,
+    id: "PSP-PRIV-002",
+    remediation: "Set `allowPrivilegeEscalation: false` in the securityContext of the container.",
+};
+
+rule_params! {
+    /// Requires containers to set `allowPrivilegeEscalation: false`, so that a
+    /// process cannot gain more privileges than its parent, e.g. through setuid
+    /// binaries. Corresponds to the `allowPrivilegeEscalation` field in a
+    /// PodSecurityPolicy.
+    pub struct Params {
+        enabled: false,
+        enforcement_action: Deny,
+        severity: Medium,
+        exempt_images,
+    }
+}
+
+pub fn check(pod: &apicore::PodSpec, _params: &Params, exemptions: &Exemptions) -> Vec<Finding> {
+    exemptions
+        .containers(pod)
+        .filter(allows_privilege_escalation)
+        .map(|container| {
+            let message = format!(
+                "Privilege escalation must be disabled with `allowPrivilegeEscalation: false` for {}: {}",
+                container.kind, container.name
+            );
+            Finding::container(&container, message)
+        })
+        .collect()
+}
+
+/// Sets `allowPrivilegeEscalation: false` on the containers not setting it.
+pub fn remediate(
+    pod: &apicore::PodSpec,
+    _params: &Params,
+    exemptions: &Exemptions,
+    patch: &mut PodPatch,
+) {
+    for container in exemptions
+        .containers(pod)
+        .filter(allows_privilege_escalation)
+    {
+        patch.set_container(
+            &container,
+            "securityContext/allowPrivilegeEscalation",
+            json!(false),
+        );
+    }
+}
+
+fn allows_privilege_escalation(container: &ContainerView) -> bool {
+    // Privilege escalation is allowed unless explicitly disabled.
+    container
+        .security_context
+        .and_then(|security_context| security_context.allow_privilege_escalation)
+        != Some(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pod_with(allow_privilege_escalation: Option<bool>) -> apicore::PodSpec {
+        apicore::PodSpec {
+            containers: vec![apicore::Container {
+                name: "nginx".to_string(),
+                security_context: Some(apicore::SecurityContext {
+                    allow_privilege_escalation,
+                    ..apicore::SecurityContext::default()
+                }),
+                ..apicore::Container::default()
+            }],
+            ..apicore::PodSpec::default()
+        }
+    }
+
+    fn check_pod(pod: &apicore::PodSpec) -> Vec<String> {
+        check(pod, &Params::default(), &Exemptions::none())
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect()
+    }
+
+    #[test]
+    fn accept_container_disabling_privilege_escalation_test() {
+        assert!(check_pod(&pod_with(Some(false))).is_empty());
+    }
+
+    #[test]
+    fn reject_container_allowing_privilege_escalation_test() {
+        assert_eq!(check_pod(&pod_with(Some(true))).len(), 1);
+    }
+
+    #[test]
+    fn reject_container_not_setting_privilege_escalation_test() {
+        assert_eq!(
+            check_pod(&pod_with(None)).len(),
+            1,
+            "Privilege escalation is allowed by default and must be disabled explicitly"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use k8s_openapi::api::core::v1 as apicore;
+use serde_json::json;
+
+use super::{rule_params, Exemptions, Finding, Rule};
+use crate::pod::{self, ContainerView, PodPatch};
+
+/// Key of the rule under the `rules` parameter.
+pub const NAME: &str = "capabilities";
+
+/// Reported with the violations of the rule.
+pub const RULE: Rule = Rule {
+    name: NAME,
+    id: "PSP-PRIV-003",
+    remediation: "Only add allowed capabilities in `securityContext.capabilities.add`, and \
+                  drop the required ones, or `ALL`, in `securityContext.capabilities.drop`.",
+};
+
+rule_params! {
+    /// Restricts the Linux capabilities containers add, and requires some to be
+    /// dropped. Corresponds to the `allowedCapabilities` and
+    /// `requiredDropCapabilities` fields in a PodSecurityPolicy.
+    pub struct Params {
+        enabled: false,
+        enforcement_action: Deny,
+        severity: High,
+        exempt_images,
+        /// Capabilities containers are allowed to add. `*` allows any.
+        pub allowed_capabilities: Vec<String>,
+        /// Capabilities containers may also add in pods with
+        /// `hostUsers: false`, where they only grant privileges over the
+        /// resources of the user namespace of the pod, e.g. `NET_ADMIN` or
+        /// `SYS_ADMIN`. `*` allows any.
+        pub user_namespace_allowed_capabilities: Vec<String>,
+        /// Capabilities containers must drop. Dropping `ALL` satisfies any
+        /// entry.
+        pub required_drop_capabilities: Vec<String>,
+    }
+}
+
+pub fn check(pod: &apicore::PodSpec, params: &Params, exemptions: &Exemptions) -> Vec<Finding> {
+    let mut violations = Vec::new();
+    for container in exemptions.containers(pod) {
+        let findings = Findings::of(pod, &container, params);
+        if !findings.disallowed.is_empty() {
+            let message = format!(
+                "Capabilities {} are not allowed for {}: {}",
+                findings.disallowed.join(", "),
+                container.kind,
+                container.name
+            );
+            violations.push(Finding::container(&container, message));
+        }
+        if !findings.missing.is_empty() {
+            let message = format!(
+                "Capabilities {} must be dropped by {}: {}",
+                findings.missing.join(", "),
+                container.kind,
+                container.name
+            );
+            violations.push(Finding::container(&container, message));
+        }
+    }
+    violations
+}
+
+/// Removes the capabilities that are not allowed from the ones added, and
+/// drops the required ones.
+pub fn remediate(
+    pod: &apicore::PodSpec,
+    params: &Params,
+    exemptions: &Exemptions,
+    patch: &mut PodPatch,
+) {
+    for container in exemptions.containers(pod) {
+        let findings = Findings::of(pod, &container, params);
+        if !findings.disallowed.is_empty() {
+            let allowed: Vec<_> = findings
+                .added
+                .iter()
+                .filter(|capability| !findings.disallowed.contains(&capability.as_str()))
+                .collect();
+            patch.set_container(
+                &container,
+                "securityContext/capabilities/add",
+                json!(allowed),
+            );
+        }
+        if !findings.missing.is_empty() {
+            let mut dropped: Vec<_> = findings.dropped.iter().map(String::as_str).collect();
+            dropped.extend(&findings.missing);
+            patch.set_container(
+                &container,
+                "securityContext/capabilities/drop",
+                json!(dropped),
+            );
+        }
+    }
+}
+
+/// The capabilities of a container, and how they violate the rule.
+struct Findings<'a> {
+    added: &'a [String],
+    dropped: &'a [String],
+    /// Capabilities added but not allowed.
+    disallowed: Vec<&'a str>,
+    /// Capabilities that must be dropped but are not.
+    missing: Vec<&'a str>,
+}
+
+impl<'a> Findings<'a> {
+    fn of(pod: &apicore::PodSpec, container: &ContainerView<'a>, params: &'a Params) -> Self {
+        let capabilities = container
+            .security_context
+            .and_then(|security_context| security_context.capabilities.as_ref());
+        let added = capabilities
+            .and_then(|c| c.add.as_deref())
+            .unwrap_or_default();
+        let dropped = capabilities
+            .and_then(|c| c.drop.as_deref())
+            .unwrap_or_default();
+
+        let mut allowed = vec![params.allowed_capabilities.as_slice()];
+        if pod::has_user_namespace(pod) {
+            allowed.push(&params.user_namespace_allowed_capabilities);
+        }
+        let is_allowed = |capability: &String| {
+            allowed
+                .iter()
+                .any(|allowed| allowed.iter().any(|c| c == "*" || c == capability))
+        };
+        let disallowed = added
+            .iter()
+            .filter(|capability| !is_allowed(capability))
+            .map(String::as_str)
+            .collect();
+
+        let drops_all = dropped.iter().any(|capability| capability == "ALL");
+        let missing = params
+            .required_drop_capabilities
+            .iter()
+            .filter(|capability| !drops_all && !dropped.contains(capability))
+            .map(String::as_str)
+            .collect();
+
+        Findings {
+            added,
+            dropped,
+            disallowed,
+            missing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pod_with(add: &[&str], drop: &[&str]) -> apicore::PodSpec {
+        let to_vec =
+            |capabilities: &[&str]| Some(capabilities.iter().map(|c| c.to_string()).collect());
+        apicore::PodSpec {
+            containers: vec![apicore::Container {
+                name: "nginx".to_string(),
+                security_context: Some(apicore::SecurityContext {
+                    capabilities: Some(apicore::Capabilities {
+                        add: to_vec(add),
+                        drop: to_vec(drop),
+                    }),
+                    ..apicore::SecurityContext::default()
+                }),
+                ..apicore::Container::default()
+            }],
+            ..apicore::PodSpec::default()
+        }
+    }
+
+    fn params(allowed: &[&str], required_drop: &[&str]) -> Params {
+        Params {
+            allowed_capabilities: allowed.iter().map(|c| c.to_string()).collect(),
+            required_drop_capabilities: required_drop.iter().map(|c| c.to_string()).collect(),
+            ..Params::default()
+        }
+    }
+
+    fn check_pod(pod: &apicore::PodSpec, params: &Params) -> Vec<String> {
+        check(pod, params, &Exemptions::none())
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect()
+    }
+
+    #[test]
+    fn accept_allowed_capabilities_test() {
+        let pod = pod_with(&["NET_BIND_SERVICE"], &[]);
+        assert!(check_pod(&pod, &params(&["NET_BIND_SERVICE"], &[])).is_empty());
+        assert!(check_pod(&pod, &params(&["*"], &[])).is_empty());
+    }
+
+    #[test]
+    fn reject_disallowed_capabilities_test() {
+        let pod = pod_with(&["NET_BIND_SERVICE", "SYS_ADMIN"], &[]);
+        assert_eq!(
+            check_pod(&pod, &params(&["NET_BIND_SERVICE"], &[])),
+            vec!["Capabilities SYS_ADMIN are not allowed for container: nginx"]
+        );
+    }
+
+    #[test]
+    fn allow_namespaced_capabilities_in_user_namespace_test() {
+        let params = Params {
+            user_namespace_allowed_capabilities: vec!["SYS_ADMIN".to_string()],
+            ..params(&["NET_BIND_SERVICE"], &[])
+        };
+        let mut pod = pod_with(&["NET_BIND_SERVICE", "SYS_ADMIN"], &[]);
+        assert_eq!(check_pod(&pod, &params).len(), 1);
+
+        pod.host_users = Some(false);
+        assert!(
+            check_pod(&pod, &params).is_empty(),
+            "Capabilities are namespaced in a user namespace"
+        );
+    }
+
+    #[test]
+    fn require_dropped_capabilities_test() {
+        let required = params(&[], &["NET_RAW", "SYS_ADMIN"]);
+        assert!(check_pod(&pod_with(&[], &["ALL"]), &required).is_empty());
+        assert!(check_pod(&pod_with(&[], &["NET_RAW", "SYS_ADMIN"]), &required).is_empty());
+        assert_eq!(
+            check_pod(&pod_with(&[], &["NET_RAW"]), &required),
+            vec!["Capabilities SYS_ADMIN must be dropped by container: nginx"]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use k8s_openapi::api::core::v1 as apicore;
+use serde_json::json;
+
+use super::{rule_params, Exemptions, Finding, Rule};
+use crate::pod::PodPatch;
+
+/// Key of the rule under the `rules` parameter.
+pub const NAME: &str = "hostNamespaces";
+
+/// Reported with the violations of the rule.
+pub const RULE: Rule = Rule {
+    name: NAME,
+    id: "PSP-PRIV-004",
+    remediation: "Remove `hostPID`, `hostIPC` and `hostNetwork` from the pod spec.",
+};
+
+rule_params! {
+    /// Denies pods sharing the PID, IPC or network namespace of the host.
+    /// Corresponds to the `hostPID`, `hostIPC` and `hostNetwork` fields in a
+    /// PodSecurityPolicy.
+    pub struct Params {
+        enabled: false,
+        enforcement_action: Deny,
+        severity: High,
+    }
+}
+
+pub fn check(pod: &apicore::PodSpec, _params: &Params, _exemptions: &Exemptions) -> Vec<Finding> {
+    shared_namespaces(pod)
+        .map(|(namespace, _)| {
+            Finding::pod(format!(
+                "Sharing the host {} namespace is not allowed",
+                namespace
+            ))
+        })
+        .collect()
+}
+
+/// Stops sharing the namespaces of the host.
+pub fn remediate(
+    pod: &apicore::PodSpec,
+    _params: &Params,
+    _exemptions: &Exemptions,
+    patch: &mut PodPatch,
+) {
+    for (_, field) in shared_namespaces(pod) {
+        patch.set_pod(field, json!(false));
+    }
+}
+
+/// Returns the namespaces of the host the pod shares, with the field sharing
+/// each.
+fn shared_namespaces(pod: &apicore::PodSpec) -> impl Iterator<Item = (&'static str, &'static str)> {
+    [
+        ("PID", "hostPID", pod.host_pid),
+        ("IPC", "hostIPC", pod.host_ipc),
+        ("network", "hostNetwork", pod.host_network),
+    ]
+    .into_iter()
+    .filter(|(_, _, shared)| shared.unwrap_or(false))
+    .map(|(namespace, field, _)| (namespace, field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_pod(pod: &apicore::PodSpec) -> Vec<String> {
+        check(pod, &Params::default(), &Exemptions::none())
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect()
+    }
+
+    #[test]
+    fn accept_pod_not_sharing_host_namespaces_test() {
+        assert!(check_pod(&apicore::PodSpec::default()).is_empty());
+        assert!(check_pod(&apicore::PodSpec {
+            host_pid: Some(false),
+            host_ipc: Some(false),
+            host_network: Some(false),
+            ..apicore::PodSpec::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn reject_pod_sharing_host_namespaces_test() {
+        let violations = check_pod(&apicore::PodSpec {
+            host_pid: Some(true),
+            host_network: Some(true),
+            ..apicore::PodSpec::default()
+        });
+        assert_eq!(
+            violations,
+            vec![
+                "Sharing the host PID namespace is not allowed",
+                "Sharing the host network namespace is not allowed",
+            ]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use k8s_openapi::api::core::v1 as apicore;
+use serde_json::json;
+
+use super::{rule_params, Exemptions, Finding, Rule};
+use crate::params::glob_matches;
+use crate::pod::{self, PodPatch};
+
+/// Key of the rule under the `rules` parameter.
+pub const NAME: &str = "hostUsers";
+
+/// Reported with the violations of the rule.
+pub const RULE: Rule = Rule {
+    name: NAME,
+    id: "PSP-PRIV-006",
+    remediation: "Set `hostUsers: false` in the pod spec.",
+};
+
+rule_params! {
+    /// Requires pods to run in a user namespace with `hostUsers: false`, so
+    /// that their users, root included, map to unprivileged users of the host.
+    /// Meant for the namespaces of untrusted workloads, e.g. the ones of
+    /// tenants. The cluster must support user namespaces, from Kubernetes 1.30.
+    pub struct Params {
+        enabled: false,
+        enforcement_action: Deny,
+        severity: Medium,
+        /// The untrusted namespaces, whose pods must set `hostUsers: false`.
+        /// `*` matches any characters, as in `tenant-*`. The rule applies to
+        /// all namespaces when empty.
+        pub namespaces: Vec<String>,
+    }
+}
+
+impl Params {
+    /// Returns true if the rule applies to pods of the namespace. Objects
+    /// without a namespace are only checked when the rule applies to all
+    /// namespaces.
+    pub fn applies_to(&self, namespace: Option<&str>) -> bool {
+        if self.namespaces.is_empty() {
+            return true;
+        }
+        namespace.is_some_and(|namespace| {
+            self.namespaces
+                .iter()
+                .any(|pattern| glob_matches(pattern, namespace))
+        })
+    }
+}
+
+pub fn check(pod: &apicore::PodSpec, _params: &Params, _exemptions: &Exemptions) -> Vec<Finding> {
+    if pod::has_user_namespace(pod) {
+        return Vec::new();
+    }
+    vec![Finding::pod(
+        "Running with the users of the host is not allowed, set `hostUsers: false`".to_string(),
+    )]
+}
+
+/// Sets `hostUsers: false`.
+pub fn remediate(
+    pod: &apicore::PodSpec,
+    _params: &Params,
+    _exemptions: &Exemptions,
+    patch: &mut PodPatch,
+) {
+    if !pod::has_user_namespace(pod) {
+        patch.set_pod("hostUsers", json!(false));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_pod(host_users: Option<bool>) -> Vec<String> {
+        let pod = apicore::PodSpec {
+            host_users,
+            ..apicore::PodSpec::default()
+        };
+        check(&pod, &Params::default(), &Exemptions::none())
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect()
+    }
+
+    #[test]
+    fn require_user_namespace_test() {
+        assert!(check_pod(Some(false)).is_empty());
+        assert_eq!(
+            check_pod(None).len(),
+            1,
+            "Pods use the host users by default"
+        );
+        assert_eq!(check_pod(Some(true)).len(), 1);
+    }
+
+    #[test]
+    fn applies_to_untrusted_namespaces_test() {
+        assert!(Params::default().applies_to(None));
+        let params = Params {
+            namespaces: vec!["tenant-*".to_string()],
+            ..Params::default()
+        };
+        assert!(params.applies_to(Some("tenant-a")));
+        assert!(!params.applies_to(Some("kube-system")));
+        assert!(!params.applies_to(None));
+    }
+}
@@ -0,0 +1,568 @@
+//! The checks bundled in the module. Every rule has its own entry under the
+//! `rules` parameter, which selects whether it runs and what happens when it
+//! is violated; the results of all rules are aggregated into one decision.
+
+pub mod allow_privilege_escalation;
+pub mod capabilities;
+pub mod host_namespaces;
+pub mod host_users;
+pub mod privileged;
+pub mod run_as_non_root;
+
+use k8s_openapi::api::core::v1 as apicore;
+use policy_sdk::{EnforcementAction, Severity};
+use serde::Serialize;
+
+use crate::params::image_matches;
+use crate::pod::{self, ContainerView, PodPatch};
+use crate::Parameters;
+
+/// Settings accepted by every rule, next to its own parameters.
+pub trait RuleParams {
+    fn enabled(&self) -> bool;
+    fn enforcement_action(&self) -> EnforcementAction;
+    /// The images excluded from the rule. Rules about the whole pod take
+    /// none, as a pod cannot be exempted through one of its containers.
+    fn exempt_images(&self) -> &[String] {
+        &[]
+    }
+    fn severity(&self) -> Severity;
+    /// The link returned with the violations, if any.
+    fn documentation_url(&self) -> Option<&str>;
+}
+
+/// Defines the `Params` of a rule, with the settings every rule accepts
+/// followed by its own fields, and implements [`RuleParams`] for it:
+///
+/// ```ignore
+/// rule_params! {
+///     /// Denies ...
+///     pub struct Params {
+///         enabled: false,
+///         enforcement_action: Deny,
+///         severity: High,
+///         exempt_images,
+///         /// A field of the rule, defaulting to its `Default`.
+///         pub allowed_capabilities: Vec<String>,
+///     }
+/// }
+/// ```
+///
+/// `enabled`, `enforcement_action` and `severity` are the defaults of the
+/// rule. Rules checking containers list `exempt_images` to take an
+/// `exemptImages` setting.
+macro_rules! rule_params {
+    (
+        $(#[$attr:meta])*
+        pub struct Params {
+            enabled: $enabled:literal,
+            enforcement_action: $action:ident,
+            severity: $severity:ident,
+            exempt_images,
+            $($fields:tt)*
+        }
+    ) => {
+        rule_params! {
+            @define [exempt_images] $(#[$attr])* $enabled, $action, $severity, $($fields)*
+        }
+    };
+    (
+        $(#[$attr:meta])*
+        pub struct Params {
+            enabled: $enabled:literal,
+            enforcement_action: $action:ident,
+            severity: $severity:ident,
+            $($fields:tt)*
+        }
+    ) => {
+        rule_params! { @define [] $(#[$attr])* $enabled, $action, $severity, $($fields)* }
+    };
+    (
+        @define [$($exempt_images:ident)?]
+        $(#[$attr:meta])* $enabled:literal, $action:ident, $severity:ident,
+        $(
+            $(#[$field_attr:meta])*
+            pub $field:ident: $ty:ty,
+        )*
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, PartialEq, Eq, ::serde::Deserialize, ::schemars::JsonSchema)]
+        #[serde(rename_all = "camelCase", deny_unknown_fields, default)]
+        pub struct Params {
+            /// Whether the rule runs.
+            pub enabled: bool,
+            /// What happens when the rule is violated: `deny`, `warn` or
+            /// `dryrun`.
+            pub enforcement_action: ::policy_sdk::EnforcementAction,
+            $(
+                /// Images excluded from this rule only, in the format of the
+                /// top-level `exemptImages`.
+                pub $exempt_images: Vec<String>,
+            )?
+            /// How serious a violation of the rule is: `low`, `medium`,
+            /// `high` or `critical`. Defaults to `critical` for
+            /// `privileged`, `high` for `capabilities` and `hostNamespaces`,
+            /// and `medium` for the other rules.
+            pub severity: ::policy_sdk::Severity,
+            /// A link returned with the violations of the rule, e.g. to the
+            /// page of the developer portal explaining it.
+            pub documentation_url: String,
+            $(
+                $(#[$field_attr])*
+                pub $field: $ty,
+            )*
+        }
+
+        impl Default for Params {
+            fn default() -> Self {
+                Params {
+                    enabled: $enabled,
+                    enforcement_action: ::policy_sdk::EnforcementAction::$action,
+                    $($exempt_images: Vec::new(),)?
+                    severity: ::policy_sdk::Severity::$severity,
+                    documentation_url: String::new(),
+                    $($field: Default::default(),)*
+                }
+            }
+        }
+
+        impl $crate::rules::RuleParams for Params {
+            fn enabled(&self) -> bool {
+                self.enabled
+            }
+
+            fn enforcement_action(&self) -> ::policy_sdk::EnforcementAction {
+                self.enforcement_action
+            }
+
+            $(
+                fn exempt_images(&self) -> &[String] {
+                    &self.$exempt_images
+                }
+            )?
+
+            fn severity(&self) -> ::policy_sdk::Severity {
+                self.severity
+            }
+
+            fn documentation_url(&self) -> Option<&str> {
+                Some(self.documentation_url.as_str()).filter(|url| !url.is_empty())
+            }
+        }
+    };
+}
+use rule_params;
+
+/// What the violations of a rule are reported with, besides their message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    /// Key of the rule under the `rules` parameter.
+    pub name: &'static str,
+    /// Identifier of the rule in its violations, which unlike their messages
+    /// stays the same across releases.
+    pub id: &'static str,
+    /// How to fix a violation of the rule.
+    pub remediation: &'static str,
+}
+
+/// Containers left out of a rule because of their image, the
+/// `exemptImages` of the constraint plus the ones of the rule, or by name,
+/// see [`crate::exemption`].
+pub struct Exemptions<'a> {
+    global: &'a [String],
+    rule: &'a [String],
+    containers: &'a [String],
+}
+
+impl<'a> Exemptions<'a> {
+    fn new<P: RuleParams>(
+        params: &'a Parameters,
+        rule_params: &'a P,
+        containers: &'a [String],
+    ) -> Self {
+        Exemptions {
+            global: &params.exempt_images,
+            rule: rule_params.exempt_images(),
+            containers,
+        }
+    }
+
+    /// Exempts nothing, for the tests of the rules.
+    #[cfg(test)]
+    pub fn none() -> Self {
+        Exemptions {
+            global: &[],
+            rule: &[],
+            containers: &[],
+        }
+    }
+
+    pub fn is_exempt(&self, container: &ContainerView) -> bool {
+        image_matches(self.global, container.image)
+            || image_matches(self.rule, container.image)
+            || self.containers.iter().any(|name| name == container.name)
+    }
+
+    /// Iterates over the containers of the pod the rule applies to.
+    pub fn containers<'p>(
+        &'p self,
+        pod: &'p apicore::PodSpec,
+    ) -> impl Iterator<Item = ContainerView<'p>> + 'p {
+        pod::containers(pod).filter(move |container| !self.is_exempt(container))
+    }
+}
+
+/// A violation found by a rule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    /// The offending container, `None` when the violation is about the pod
+    /// itself, e.g. sharing a namespace of the host.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    pub message: String,
+}
+
+impl Finding {
+    pub fn container(container: &ContainerView, message: String) -> Self {
+        Finding {
+            container: Some(container.name.to_string()),
+            message,
+        }
+    }
+
+    pub fn pod(message: String) -> Self {
+        Finding {
+            container: None,
+            message,
+        }
+    }
+}
+
+/// Violations reported by one rule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleResult {
+    pub rule: &'static str,
+    pub id: &'static str,
+    pub action: EnforcementAction,
+    pub severity: Severity,
+    /// Returned with the violations, left out of the trace.
+    #[serde(skip)]
+    pub remediation: &'static str,
+    #[serde(skip)]
+    pub documentation_url: Option<String>,
+    pub violations: Vec<Finding>,
+    /// The containers left out of the rule, by image or by name.
+    pub exempt_containers: Vec<String>,
+}
+
+/// A rule returns a finding for every violation it finds in the pod.
+type Check<P> = fn(&apicore::PodSpec, &P, &Exemptions) -> Vec<Finding>;
+
+/// A rule sets the fields fixing the violations it finds in the pod.
+type Remediate<P> = fn(&apicore::PodSpec, &P, &Exemptions, &mut PodPatch);
+
+/// Runs every enabled rule against the pod of the namespace, leaving out the
+/// exempted containers.
+pub fn evaluate(
+    pod: &apicore::PodSpec,
+    namespace: Option<&str>,
+    params: &Parameters,
+    exempt_containers: &[String],
+) -> Vec<RuleResult> {
+    let rules = &params.rules;
+    let mut results = evaluate_containers(pod, params, exempt_containers);
+    results.extend(
+        [
+            run(
+                &host_namespaces::RULE,
+                pod,
+                params,
+                exempt_containers,
+                &rules.host_namespaces,
+                host_namespaces::check,
+            ),
+            rules
+                .host_users
+                .applies_to(namespace)
+                .then(|| {
+                    run(
+                        &host_users::RULE,
+                        pod,
+                        params,
+                        exempt_containers,
+                        &rules.host_users,
+                        host_users::check,
+                    )
+                })
+                .flatten(),
+        ]
+        .into_iter()
+        .flatten(),
+    );
+    results
+}
+
+/// Runs the enabled rules about the containers of the pod, leaving out the
+/// rules about the pod itself, `hostNamespaces` and `hostUsers`. Used for the
+/// ephemeral containers added to a running pod, which can't change the pod.
+pub fn evaluate_containers(
+    pod: &apicore::PodSpec,
+    params: &Parameters,
+    exempt_containers: &[String],
+) -> Vec<RuleResult> {
+    let rules = &params.rules;
+    [
+        run(
+            &privileged::RULE,
+            pod,
+            params,
+            exempt_containers,
+            &rules.privileged,
+            privileged::check,
+        ),
+        run(
+            &allow_privilege_escalation::RULE,
+            pod,
+            params,
+            exempt_containers,
+            &rules.allow_privilege_escalation,
+            allow_privilege_escalation::check,
+        ),
+        run(
+            &capabilities::RULE,
+            pod,
+            params,
+            exempt_containers,
+            &rules.capabilities,
+            capabilities::check,
+        ),
+        run(
+            &run_as_non_root::RULE,
+            pod,
+            params,
+            exempt_containers,
+            &rules.run_as_non_root,
+            run_as_non_root::check,
+        ),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn run<P: RuleParams>(
+    rule: &Rule,
+    pod: &apicore::PodSpec,
+    params: &Parameters,
+    exempt_containers: &[String],
+    rule_params: &P,
+    check: Check<P>,
+) -> Option<RuleResult> {
+    if !rule_params.enabled() {
+        return None;
+    }
+    let exemptions = Exemptions::new(params, rule_params, exempt_containers);
+    Some(RuleResult {
+        rule: rule.name,
+        id: rule.id,
+        action: rule_params.enforcement_action(),
+        severity: rule_params.severity(),
+        remediation: rule.remediation,
+        documentation_url: rule_params.documentation_url().map(str::to_string),
+        violations: check(pod, rule_params, &exemptions),
+        exempt_containers: pod::containers(pod)
+            .filter(|container| exemptions.is_exempt(container))
+            .map(|container| container.name.to_string())
+            .collect(),
+    })
+}
+
+/// Runs the remediation of every enabled rule. Rules in `warn` or `dryrun`
+/// mode are left out, as their violations don't affect the request, and so
+/// are the exempted containers.
+pub fn remediate(
+    pod: &apicore::PodSpec,
+    namespace: Option<&str>,
+    params: &Parameters,
+    exempt_containers: &[String],
+    patch: &mut PodPatch,
+) {
+    let rules = &params.rules;
+    fix(
+        pod,
+        params,
+        exempt_containers,
+        &rules.privileged,
+        privileged::remediate,
+        patch,
+    );
+    fix(
+        pod,
+        params,
+        exempt_containers,
+        &rules.allow_privilege_escalation,
+        allow_privilege_escalation::remediate,
+        patch,
+    );
+    fix(
+        pod,
+        params,
+        exempt_containers,
+        &rules.capabilities,
+        capabilities::remediate,
+        patch,
+    );
+    fix(
+        pod,
+        params,
+        exempt_containers,
+        &rules.host_namespaces,
+        host_namespaces::remediate,
+        patch,
+    );
+    fix(
+        pod,
+        params,
+        exempt_containers,
+        &rules.run_as_non_root,
+        run_as_non_root::remediate,
+        patch,
+    );
+    if rules.host_users.applies_to(namespace) {
+        fix(
+            pod,
+            params,
+            exempt_containers,
+            &rules.host_users,
+            host_users::remediate,
+            patch,
+        );
+    }
+}
+
+fn fix<P: RuleParams>(
+    pod: &apicore::PodSpec,
+    params: &Parameters,
+    exempt_containers: &[String],
+    rule_params: &P,
+    remediate: Remediate<P>,
+    patch: &mut PodPatch,
+) {
+    if rule_params.enabled() && rule_params.enforcement_action() == EnforcementAction::Deny {
+        let exemptions = Exemptions::new(params, rule_params, exempt_containers);
+        remediate(pod, rule_params, &exemptions, patch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use policy_sdk::parse_parameters;
+
+    fn host_network_pod() -> apicore::PodSpec {
+        apicore::PodSpec {
+            host_network: Some(true),
+            containers: vec![apicore::Container {
+                name: "nginx".to_string(),
+                security_context: Some(apicore::SecurityContext {
+                    privileged: Some(true),
+                    ..apicore::SecurityContext::default()
+                }),
+                ..apicore::Container::default()
+            }],
+            ..apicore::PodSpec::default()
+        }
+    }
+
+    #[test]
+    fn only_privileged_rule_runs_by_default_test() {
+        let results = evaluate(&host_network_pod(), None, &Parameters::default(), &[]);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].rule, privileged::NAME);
+        assert_eq!(results[0].action, EnforcementAction::Deny);
+        assert_eq!(results[0].violations.len(), 1);
+    }
+
+    /// A rule rolled out in `warn` mode unless its `enforcementAction` says
+    /// otherwise.
+    mod warn_by_default {
+        use super::*;
+
+        rule_params! {
+            /// Reports every container.
+            pub struct Params {
+                enabled: true,
+                enforcement_action: Warn,
+                severity: Low,
+            }
+        }
+
+        pub fn check(
+            pod: &apicore::PodSpec,
+            _params: &Params,
+            exemptions: &Exemptions,
+        ) -> Vec<Finding> {
+            exemptions
+                .containers(pod)
+                .map(|container| Finding::container(&container, "Reported".to_string()))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn rule_default_action_test() -> Result<()> {
+        let run_rule = |rule_params: &warn_by_default::Params| {
+            let pod = host_network_pod();
+            let params = Parameters::default();
+            let check = warn_by_default::check;
+            run(&privileged::RULE, &pod, &params, &[], rule_params, check).map(|r| r.action)
+        };
+        let rule_params = parse_parameters::<warn_by_default::Params>("{}")?;
+        assert_eq!(run_rule(&rule_params), Some(EnforcementAction::Warn));
+        let rule_params =
+            parse_parameters::<warn_by_default::Params>(r#"{"enforcementAction":"deny"}"#)?;
+        assert_eq!(run_rule(&rule_params), Some(EnforcementAction::Deny));
+        Ok(())
+    }
+
+    #[test]
+    fn parameters_select_rules_test() -> Result<()> {
+        let params = parse_parameters::<Parameters>(
+            r#"{"rules":{"privileged":{"enabled":false},"hostNamespaces":{"enabled":true,"enforcementAction":"dryrun"}}}"#,
+        )?;
+        let results = evaluate(&host_network_pod(), None, &params, &[]);
+        assert_eq!(
+            results,
+            vec![RuleResult {
+                rule: host_namespaces::NAME,
+                id: host_namespaces::RULE.id,
+                action: EnforcementAction::Dryrun,
+                severity: Severity::High,
+                remediation: host_namespaces::RULE.remediation,
+                documentation_url: None,
+                violations: vec![Finding::pod(
+                    "Sharing the host network namespace is not allowed".to_string()
+                )],
+                exempt_containers: Vec::new(),
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rule_exempt_images_only_apply_to_their_rule_test() -> Result<()> {
+        let params = parse_parameters::<Parameters>(
+            r#"{"rules":{"privileged":{"exemptImages":["nginx"]},"allowPrivilegeEscalation":{"enabled":true}}}"#,
+        )?;
+        let mut pod = host_network_pod();
+        pod.containers[0].image = Some("nginx".to_string());
+        let results = evaluate(&pod, None, &params, &[]);
+        assert!(results[0].violations.is_empty());
+        assert_eq!(results[0].exempt_containers, vec!["nginx"]);
+        assert_eq!(results[1].rule, allow_privilege_escalation::NAME);
+        assert_eq!(results[1].violations.len(), 1);
+        Ok(())
+    }
+}
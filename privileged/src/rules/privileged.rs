@@ -0,0 +1,191 @@
+use k8s_openapi::api::core::v1 as apicore;
+use serde_json::json;
+
+use super::{rule_params, Exemptions, Finding, Rule};
+use crate::pod::{ContainerView, PodPatch};
+
+/// Key of the rule under the `rules` parameter.
+pub const NAME: &str = "privileged";
+
+/// Reported with the violations of the rule.
+pub const RULE: Rule = Rule {
+    name: NAME,
+    id: "PSP-PRIV-001",
+    remediation: "Remove `privileged: true` from the securityContext of the container.",
+};
+
+rule_params! {
+    /// Denies containers running in privileged mode, which gives them access to
+    /// all devices of the host. Corresponds to the `privileged` field in a
+    /// PodSecurityPolicy. Unlike the other rules, it runs by default.
+    pub struct Params {
+        enabled: true,
+        enforcement_action: Deny,
+        severity: Critical,
+        exempt_images,
+    }
+}
+
+pub fn check(pod: &apicore::PodSpec, _params: &Params, exemptions: &Exemptions) -> Vec<Finding> {
+    exemptions
+        .containers(pod)
+        .filter(is_privileged)
+        .map(|container| {
+            let message = format!(
+                "Privileged {} is not allowed: {}",
+                container.kind, container.name
+            );
+            Finding::container(&container, message)
+        })
+        .collect()
+}
+
+/// Sets `privileged: false` on the privileged containers.
+pub fn remediate(
+    pod: &apicore::PodSpec,
+    _params: &Params,
+    exemptions: &Exemptions,
+    patch: &mut PodPatch,
+) {
+    for container in exemptions.containers(pod).filter(is_privileged) {
+        patch.set_container(&container, "securityContext/privileged", json!(false));
+    }
+}
+
+fn is_privileged(container: &ContainerView) -> bool {
+    container
+        .security_context
+        .and_then(|security_context| security_context.privileged)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pod::ContainerKind;
+    use anyhow::Result;
+
+    fn validate_container(container: &apicore::Container) -> bool {
+        !is_privileged(&ContainerView::from_container(
+            ContainerKind::Container,
+            0,
+            container,
+        ))
+    }
+
+    fn validate_ephemeral_container(container: &apicore::EphemeralContainer) -> bool {
+        !is_privileged(&ContainerView::from_ephemeral_container(0, container))
+    }
+
+    #[test]
+    fn accept_container_is_not_privileged_test() -> Result<()> {
+        assert!(
+            validate_container(&apicore::Container {
+                security_context: Some(apicore::SecurityContext {
+                    privileged: Some(false),
+                    ..apicore::SecurityContext::default()
+                }),
+                ..apicore::Container::default()
+            }),
+            "Non privileged container should be accepted by the validator"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn accept_container_with_no_security_context() -> Result<()> {
+        assert!(
+            validate_container(&apicore::Container {
+                ..apicore::Container::default()
+            }),
+            "Non privileged container should be accepted by the validator"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_privileged_container_test() -> Result<()> {
+        assert!(
+            !validate_container(&apicore::Container {
+                security_context: Some(apicore::SecurityContext {
+                    privileged: Some(true),
+                    ..apicore::SecurityContext::default()
+                }),
+                ..apicore::Container::default()
+            }),
+            "Privileged container should be rejected by the validator"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn accept_privileged_container_when_privileged_is_none_test() -> Result<()> {
+        assert!(
+            validate_container(&apicore::Container {
+                security_context: Some(apicore::SecurityContext {
+                    privileged: None,
+                    ..apicore::SecurityContext::default()
+                }),
+                ..apicore::Container::default()
+            }),
+            "Privileged container should be accepted by the validator when there is no 'privileged' configuration. The default behaviour is disable privileged containers"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn accept_ephemeral_container_is_not_privileged_test() -> Result<()> {
+        assert!(
+            validate_ephemeral_container(&apicore::EphemeralContainer {
+                security_context: Some(apicore::SecurityContext {
+                    privileged: Some(false),
+                    ..apicore::SecurityContext::default()
+                }),
+                ..apicore::EphemeralContainer::default()
+            }),
+            "Non privileged container should be accepted by the validator"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn accept_ephemeral_container_with_no_security_context() -> Result<()> {
+        assert!(
+            validate_ephemeral_container(&apicore::EphemeralContainer {
+                ..apicore::EphemeralContainer::default()
+            }),
+            "Non privileged container should be accepted by the validator"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_privileged_ephemeral_container_test() -> Result<()> {
+        assert!(
+            !validate_ephemeral_container(&apicore::EphemeralContainer {
+                security_context: Some(apicore::SecurityContext {
+                    privileged: Some(true),
+                    ..apicore::SecurityContext::default()
+                }),
+                ..apicore::EphemeralContainer::default()
+            }),
+            "Privileged container should be rejected by the validator"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn accept_privileged_ephemeral_container_when_privileged_is_none_test() -> Result<()> {
+        assert!(
+            validate_ephemeral_container(&apicore::EphemeralContainer {
+                security_context: Some(apicore::SecurityContext {
+                    privileged: None,
+                    ..apicore::SecurityContext::default()
+                }),
+                ..apicore::EphemeralContainer::default()
+            }),
+            "Privileged container should be accepted by the validator when there is no 'privileged' configuration. The default behaviour is disable privileged containers"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use k8s_openapi::api::core::v1 as apicore;
+use serde_json::json;
+
+use super::{rule_params, Exemptions, Finding, Rule};
+use crate::pod::{self, ContainerView, PodPatch};
+
+/// Key of the rule under the `rules` parameter.
+pub const NAME: &str = "runAsNonRoot";
+
+/// Reported with the violations of the rule.
+pub const RULE: Rule = Rule {
+    name: NAME,
+    id: "PSP-PRIV-005",
+    remediation: "Set `runAsNonRoot: true` or a non-zero `runAsUser` in the securityContext of \
+                  the container or of the pod.",
+};
+
+rule_params! {
+    /// Requires containers to run as a non-root user: either with a non-zero
+    /// `runAsUser`, or with `runAsNonRoot: true` and no `runAsUser: 0`. Values
+    /// set on the container take precedence over the pod security context.
+    /// Corresponds to the `MustRunAsNonRoot` rule of `runAsUser` in a
+    /// PodSecurityPolicy. Like the Pod Security Standards, the rule can be
+    /// relaxed for pods with `hostUsers: false`, whose root user is not the one
+    /// of the host.
+    pub struct Params {
+        enabled: false,
+        enforcement_action: Deny,
+        severity: Medium,
+        exempt_images,
+        /// Allows containers to run as root in pods with `hostUsers: false`,
+        /// where root maps to an unprivileged user of the host. Off by default,
+        /// so that enabling user namespaces does not loosen the rule.
+        pub allow_root_in_user_namespace: bool,
+    }
+}
+
+pub fn check(pod: &apicore::PodSpec, params: &Params, exemptions: &Exemptions) -> Vec<Finding> {
+    if !applies_to(pod, params) {
+        return Vec::new();
+    }
+    exemptions
+        .containers(pod)
+        .filter(|container| runs_as_root(pod, container))
+        .map(|container| {
+            let message = format!(
+                "Running as root is not allowed for {}: {}, set `runAsNonRoot: true` or a non-zero `runAsUser`",
+                container.kind, container.name
+            );
+            Finding::container(&container, message)
+        })
+        .collect()
+}
+
+/// Sets `runAsNonRoot: true` on the containers that may run as root. The
+/// containers explicitly running as user 0 are left alone: there is no user
+/// to pick for them, they are still denied.
+pub fn remediate(
+    pod: &apicore::PodSpec,
+    params: &Params,
+    exemptions: &Exemptions,
+    patch: &mut PodPatch,
+) {
+    if !applies_to(pod, params) {
+        return;
+    }
+    for container in exemptions
+        .containers(pod)
+        .filter(|container| runs_as_root(pod, container))
+    {
+        if run_as(pod, &container).0.is_none() {
+            patch.set_container(&container, "securityContext/runAsNonRoot", json!(true));
+        }
+    }
+}
+
+/// Returns false for the pods running in a user namespace when root is
+/// allowed there.
+fn applies_to(pod: &apicore::PodSpec, params: &Params) -> bool {
+    !(params.allow_root_in_user_namespace && pod::has_user_namespace(pod))
+}
+
+fn runs_as_root(pod: &apicore::PodSpec, container: &ContainerView) -> bool {
+    match run_as(pod, container) {
+        (Some(0), _) => true,
+        (Some(_), _) => false,
+        (None, run_as_non_root) => run_as_non_root != Some(true),
+    }
+}
+
+/// Returns the effective `runAsUser` and `runAsNonRoot` of the container.
+fn run_as(pod: &apicore::PodSpec, container: &ContainerView) -> (Option<i64>, Option<bool>) {
+    let security_context = container.effective_security_context(pod);
+    (
+        security_context.run_as_user,
+        security_context.run_as_non_root,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pod_with(
+        pod: Option<apicore::PodSecurityContext>,
+        container: Option<apicore::SecurityContext>,
+    ) -> apicore::PodSpec {
+        apicore::PodSpec {
+            security_context: pod,
+            containers: vec![apicore::Container {
+                name: "nginx".to_string(),
+                security_context: container,
+                ..apicore::Container::default()
+            }],
+            ..apicore::PodSpec::default()
+        }
+    }
+
+    fn check_pod(pod: &apicore::PodSpec) -> Vec<String> {
+        check(pod, &Params::default(), &Exemptions::none())
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect()
+    }
+
+    #[test]
+    fn accept_non_root_container_test() {
+        let non_root = apicore::SecurityContext {
+            run_as_non_root: Some(true),
+            ..apicore::SecurityContext::default()
+        };
+        assert!(check_pod(&pod_with(None, Some(non_root))).is_empty());
+
+        let non_zero_user = apicore::SecurityContext {
+            run_as_user: Some(1000),
+            ..apicore::SecurityContext::default()
+        };
+        assert!(check_pod(&pod_with(None, Some(non_zero_user))).is_empty());
+    }
+
+    #[test]
+    fn accept_non_root_pod_test() {
+        let non_root = apicore::PodSecurityContext {
+            run_as_non_root: Some(true),
+            ..apicore::PodSecurityContext::default()
+        };
+        assert!(check_pod(&pod_with(Some(non_root), None)).is_empty());
+    }
+
+    #[test]
+    fn reject_root_container_test() {
+        assert_eq!(check_pod(&pod_with(None, None)).len(), 1);
+
+        let root_user = apicore::SecurityContext {
+            run_as_non_root: Some(true),
+            run_as_user: Some(0),
+            ..apicore::SecurityContext::default()
+        };
+        assert_eq!(check_pod(&pod_with(None, Some(root_user))).len(), 1);
+    }
+
+    #[test]
+    fn accept_root_in_user_namespace_test() {
+        let mut pod = pod_with(None, None);
+        pod.host_users = Some(false);
+        assert_eq!(
+            check_pod(&pod).len(),
+            1,
+            "Root must only be allowed in user namespaces when asked"
+        );
+
+        let params = Params {
+            allow_root_in_user_namespace: true,
+            ..Params::default()
+        };
+        assert!(check(&pod, &params, &Exemptions::none()).is_empty());
+    }
+
+    #[test]
+    fn container_overrides_pod_test() {
+        let pod = apicore::PodSecurityContext {
+            run_as_non_root: Some(true),
+            ..apicore::PodSecurityContext::default()
+        };
+        let container = apicore::SecurityContext {
+            run_as_non_root: Some(false),
+            ..apicore::SecurityContext::default()
+        };
+        assert_eq!(check_pod(&pod_with(Some(pod), Some(container))).len(), 1);
+    }
+}
@@ -0,0 +1,156 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use policy_sdk::schema_for;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::Parameters;
+
+/// Name of the ConstraintTemplate.
+pub const NAME: &str = "k8spspprivilegedcontainer";
+
+/// Kind of the constraints created from the template.
+pub const KIND: &str = "K8sPSPPrivilegedContainer";
+
+/// Human readable title of the policy.
+pub const TITLE: &str = "Privileged Container";
+
+/// Description shared by the template and its parameter schema.
+pub const DESCRIPTION: &str = "Controls the ability of any container to enable privileged mode. \
+Corresponds to the `privileged` field in a PodSecurityPolicy. For more information, see \
+https://kubernetes.io/docs/concepts/policy/pod-security-policy/#privileged";
+
+/// Gatekeeper target the policy is evaluated for.
+const TARGET: &str = "admission.k8s.gatekeeper.sh";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConstraintTemplate {
+    api_version: &'static str,
+    kind: &'static str,
+    metadata: Metadata,
+    spec: TemplateSpec,
+}
+
+#[derive(Debug, Serialize)]
+struct Metadata {
+    name: &'static str,
+    annotations: Annotations,
+}
+
+#[derive(Debug, Serialize)]
+struct Annotations {
+    #[serde(rename = "metadata.gatekeeper.sh/title")]
+    title: &'static str,
+    description: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct TemplateSpec {
+    crd: Crd,
+    targets: Vec<Target>,
+}
+
+#[derive(Debug, Serialize)]
+struct Crd {
+    spec: CrdSpec,
+}
+
+#[derive(Debug, Serialize)]
+struct CrdSpec {
+    names: Names,
+    validation: Validation,
+}
+
+#[derive(Debug, Serialize)]
+struct Names {
+    kind: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct Validation {
+    #[serde(rename = "openAPIV3Schema")]
+    open_api_v3_schema: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Target {
+    target: &'static str,
+    /// The experimental Gatekeeper reads the base64 encoded module from the
+    /// field otherwise holding the Rego source.
+    rego: String,
+}
+
+impl ConstraintTemplate {
+    /// Builds the ConstraintTemplate embedding the given `policy.wasm`, with
+    /// the parameter schema derived from [`Parameters`].
+    pub fn new(wasm: &[u8]) -> Self {
+        let mut schema = schema_for::<Parameters>();
+        schema["description"] = Value::from(DESCRIPTION);
+
+        ConstraintTemplate {
+            api_version: "templates.gatekeeper.sh/v1",
+            kind: "ConstraintTemplate",
+            metadata: Metadata {
+                name: NAME,
+                annotations: Annotations {
+                    title: TITLE,
+                    description: DESCRIPTION,
+                },
+            },
+            spec: TemplateSpec {
+                crd: Crd {
+                    spec: CrdSpec {
+                        names: Names { kind: KIND },
+                        validation: Validation {
+                            open_api_v3_schema: schema,
+                        },
+                    },
+                },
+                targets: vec![Target {
+                    target: TARGET,
+                    rego: STANDARD.encode(wasm),
+                }],
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use std::fs;
+    use std::path::Path;
+
+    fn read_checked_in_template() -> Result<Value> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("gatekeeper/template.yaml");
+        Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    #[test]
+    fn checked_in_template_schema_matches_parameters_test() -> Result<()> {
+        let template = read_checked_in_template()?;
+        let generated = serde_json::to_value(ConstraintTemplate::new(&[]))?;
+        assert_eq!(
+            template["spec"]["crd"]["spec"]["validation"]["openAPIV3Schema"],
+            generated["spec"]["crd"]["spec"]["validation"]["openAPIV3Schema"],
+            "gatekeeper/template.yaml is out of date, regenerate it with `make template`"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn checked_in_template_embeds_policy_wasm_test() -> Result<()> {
+        let template = read_checked_in_template()?;
+        let wasm = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("policy.wasm"))?;
+        let rego = template["spec"]["targets"][0]["rego"]
+            .as_str()
+            .unwrap_or_default();
+        assert!(
+            STANDARD.decode(rego.trim())? == wasm,
+            "gatekeeper/template.yaml does not embed policy.wasm, regenerate it with `make template`"
+        );
+        Ok(())
+    }
+}
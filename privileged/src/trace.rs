@@ -0,0 +1,103 @@
+//! The trace of a decision, returned in the `trace` of the `validate` result
+//! when the `explain` parameter is set, to see how a disputed decision was
+//! reached.
+
+use k8s_openapi::api::core::v1 as apicore;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::exemption::ContainerExemption;
+use crate::pod;
+use crate::rules::RuleResult;
+
+/// How a decision was reached.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Trace {
+    /// Why the rules did not run, e.g. an exempted namespace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+    /// The containers of the pod the rules ran against.
+    pub containers: Vec<ContainerTrace>,
+    /// The rules evaluated, in order, with the violations they found.
+    pub rules: Vec<RuleResult>,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Allowed,
+    Denied,
+}
+
+impl Outcome {
+    fn of(allowed: bool) -> Self {
+        if allowed {
+            Outcome::Allowed
+        } else {
+            Outcome::Denied
+        }
+    }
+}
+
+/// A container visited by the rules.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerTrace {
+    pub name: String,
+    /// e.g. `init container`.
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// The exemption granted to the container by annotation, see
+    /// [`crate::exemption`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exemption: Option<String>,
+    /// The security context the container runs with, its own completed by
+    /// the one of the pod.
+    pub effective_security_context: apicore::SecurityContext,
+}
+
+impl Trace {
+    /// The trace of a decision taken without running the rules.
+    pub fn skipped(reason: impl Into<String>, allowed: bool) -> Self {
+        Trace {
+            skipped: Some(reason.into()),
+            containers: Vec::new(),
+            rules: Vec::new(),
+            outcome: Outcome::of(allowed),
+        }
+    }
+
+    /// The trace of the rules run against the pod.
+    pub fn new(
+        pod: &apicore::PodSpec,
+        results: &[RuleResult],
+        exemptions: &[ContainerExemption],
+        allowed: bool,
+    ) -> Self {
+        let containers = pod::containers(pod)
+            .map(|container| ContainerTrace {
+                name: container.name.to_string(),
+                kind: container.kind.to_string(),
+                image: container.image.map(str::to_string),
+                exemption: exemptions
+                    .iter()
+                    .find(|exemption| exemption.container == container.name)
+                    .map(|exemption| exemption.to_exemption().message),
+                effective_security_context: container.effective_security_context(pod),
+            })
+            .collect();
+        Trace {
+            skipped: None,
+            containers,
+            rules: results.to_vec(),
+            outcome: Outcome::of(allowed),
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).expect("serializing a trace")
+    }
+}
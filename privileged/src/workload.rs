@@ -0,0 +1,163 @@
+use anyhow::{anyhow, Result};
+use k8s_openapi::api::core::v1 as apicore;
+use policy_sdk::metadata::MatchKinds;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// A kind of object holding a pod spec.
+pub struct Workload {
+    pub api_group: &'static str,
+    pub kind: &'static str,
+    /// Where the pod spec lives in objects of the kind, as a JSON pointer:
+    /// pods hold it directly, controllers in their pod template.
+    pub pod_spec: &'static str,
+}
+
+/// The kinds the policy validates, grouped by API group.
+pub const WORKLOADS: &[Workload] = &[
+    Workload {
+        api_group: "",
+        kind: "Pod",
+        pod_spec: "/spec",
+    },
+    Workload {
+        api_group: "",
+        kind: "PodTemplate",
+        pod_spec: "/template/spec",
+    },
+    Workload {
+        api_group: "",
+        kind: "ReplicationController",
+        pod_spec: "/spec/template/spec",
+    },
+    Workload {
+        api_group: "apps",
+        kind: "Deployment",
+        pod_spec: "/spec/template/spec",
+    },
+    Workload {
+        api_group: "apps",
+        kind: "StatefulSet",
+        pod_spec: "/spec/template/spec",
+    },
+    Workload {
+        api_group: "apps",
+        kind: "DaemonSet",
+        pod_spec: "/spec/template/spec",
+    },
+    Workload {
+        api_group: "apps",
+        kind: "ReplicaSet",
+        pod_spec: "/spec/template/spec",
+    },
+    Workload {
+        api_group: "batch",
+        kind: "Job",
+        pod_spec: "/spec/template/spec",
+    },
+    Workload {
+        api_group: "batch",
+        kind: "CronJob",
+        pod_spec: "/spec/jobTemplate/spec/template/spec",
+    },
+];
+
+/// Returns the kinds of [`WORKLOADS`] in the format of a constraint's
+/// `match.kinds`, one entry per API group.
+pub fn match_kinds() -> Vec<MatchKinds> {
+    let mut kinds: Vec<MatchKinds> = Vec::new();
+    for workload in WORKLOADS {
+        match kinds.last_mut() {
+            Some(last) if last.api_groups == [workload.api_group] => last.kinds.push(workload.kind),
+            _ => kinds.push(MatchKinds {
+                api_groups: vec![workload.api_group],
+                kinds: vec![workload.kind],
+            }),
+        }
+    }
+    kinds
+}
+
+/// Returns where the pod spec lives in objects of the given kind, see
+/// [`Workload::pod_spec`].
+pub fn pod_spec_pointer(kind: &str) -> Option<&'static str> {
+    WORKLOADS
+        .iter()
+        .find(|workload| workload.kind == kind)
+        .map(|workload| workload.pod_spec)
+}
+
+/// Extracts the pod spec of a pod or of a workload creating pods. Returns
+/// `None` for other kinds, and for objects without a spec.
+pub fn pod_spec(object: &Value) -> Option<Result<apicore::PodSpec>> {
+    let kind = object.get("kind").and_then(Value::as_str)?;
+    let spec = object.pointer(pod_spec_pointer(kind)?)?;
+    Some(apicore::PodSpec::deserialize(spec).map_err(|e| anyhow!("invalid {}: {}", kind, e)))
+}
+
+/// Returns the metadata of the pod, or of the pod template of a workload.
+pub fn pod_metadata(object: &Value) -> Option<&Value> {
+    let kind = object.get("kind").and_then(Value::as_str)?;
+    let template = pod_spec_pointer(kind)?.strip_suffix("/spec")?;
+    object.pointer(&format!("{}/metadata", template))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn container_names(object: Value) -> Option<Vec<String>> {
+        let spec = pod_spec(&object)?.unwrap();
+        Some(spec.containers.into_iter().map(|c| c.name).collect())
+    }
+
+    #[test]
+    fn pod_spec_of_workloads_test() {
+        let spec = json!({"containers": [{"name": "app"}]});
+        let app = Some(vec!["app".to_string()]);
+        assert_eq!(container_names(json!({"kind": "Pod", "spec": spec})), app);
+        assert_eq!(
+            container_names(json!({"kind": "Deployment", "spec": {"template": {"spec": spec}}})),
+            app
+        );
+        assert_eq!(
+            container_names(json!({
+                "kind": "CronJob",
+                "spec": {"jobTemplate": {"spec": {"template": {"spec": spec}}}}
+            })),
+            app
+        );
+    }
+
+    #[test]
+    fn pod_spec_keeps_recent_fields_test() {
+        let pod = json!({"kind": "Pod", "spec": {
+            "hostUsers": false,
+            "os": {"name": "linux"},
+            "securityContext": {"appArmorProfile": {"type": "RuntimeDefault"}},
+            "containers": [{"name": "app"}],
+        }});
+        let spec = pod_spec(&pod).unwrap().unwrap();
+        assert_eq!(spec.host_users, Some(false));
+        assert_eq!(spec.os.unwrap().name, "linux");
+        assert!(
+            spec.security_context.unwrap().app_armor_profile.is_some(),
+            "Fields of the selected Kubernetes version must not be dropped"
+        );
+    }
+
+    #[test]
+    fn pod_spec_of_other_kinds_test() {
+        assert!(pod_spec(&json!({"kind": "Service", "spec": {}})).is_none());
+        assert!(pod_spec(&json!({"kind": "Pod"})).is_none());
+        assert!(pod_spec(&json!({"spec": {}})).is_none());
+        assert!(
+            matches!(
+                pod_spec(&json!({"kind": "Pod", "spec": {"containers": 1}})),
+                Some(Err(_))
+            ),
+            "An invalid spec must be reported"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Runs the `privileged-eval` command-line tool.
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+fn gatekeeper(file: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("gatekeeper")
+        .join(file)
+        .display()
+        .to_string()
+}
+
+fn privileged_eval(args: &[&str], stdin: &str) -> Result<Output> {
+    run(env!("CARGO_BIN_EXE_privileged-eval"), args, stdin)
+}
+
+fn privileged_audit(args: &[&str], stdin: &str) -> Result<Output> {
+    run(env!("CARGO_BIN_EXE_privileged-audit"), args, stdin)
+}
+
+fn run(program: &str, args: &[&str], stdin: &str) -> Result<Output> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(stdin.as_bytes())?;
+    Ok(child.wait_with_output()?)
+}
+
+const DEPLOYMENTS: &str = r#"
+apiVersion: v1
+kind: List
+items:
+  - apiVersion: apps/v1
+    kind: Deployment
+    metadata:
+      name: web
+      namespace: prod
+    spec:
+      template:
+        spec:
+          containers:
+            - name: nginx
+              image: nginx
+              securityContext:
+                privileged: true
+---
+apiVersion: v1
+kind: Service
+metadata:
+  name: web
+"#;
+
+#[test]
+fn deny_example_disallowed_test() -> Result<()> {
+    let output = privileged_eval(&[&gatekeeper("example_disallowed.yaml")], "")?;
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "A violation must fail the command"
+    );
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(
+        stdout.contains(
+            "Pod nginx-privileged-disallowed\n  Privileged container is not allowed: nginx"
+        ),
+        "unexpected output: {}",
+        stdout
+    );
+    Ok(())
+}
+
+#[test]
+fn allow_example_allowed_with_constraint_test() -> Result<()> {
+    let output = privileged_eval(
+        &[
+            "--parameters",
+            &gatekeeper("constraint.yaml"),
+            &gatekeeper("example_allowed.yaml"),
+        ],
+        "",
+    )?;
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "1 objects checked, 0 denied\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn json_output_for_workloads_from_stdin_test() -> Result<()> {
+    let output = privileged_eval(&["--output", "json", "-"], DEPLOYMENTS)?;
+    assert_eq!(output.status.code(), Some(1));
+    let results: Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(
+        results,
+        json!([{
+            "file": "-",
+            "kind": "Deployment",
+            "namespace": "prod",
+            "name": "web",
+            "allowed": false,
+            "violations": [{
+                "message": "Privileged container is not allowed: nginx",
+                "rule": "privileged",
+                "enforcementAction": "deny",
+                "id": "PSP-PRIV-001",
+                "severity": "critical",
+                "remediation": "Remove `privileged: true` from the securityContext of the container.",
+            }],
+        }]),
+        "The Service must be skipped"
+    );
+    Ok(())
+}
+
+#[test]
+fn report_unreadable_input_test() -> Result<()> {
+    let output = privileged_eval(&["does-not-exist.yaml"], "")?;
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = privileged_eval(&["--parameters", "-", "-"], r#"{"exemptImage": []}"#)?;
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)?.contains("unknown field `exemptImage`"));
+    Ok(())
+}
+
+#[test]
+fn audit_kubectl_dump_test() -> Result<()> {
+    let dump = json!({
+        "apiVersion": "v1",
+        "kind": "List",
+        "items": [
+            {
+                "kind": "Pod",
+                "metadata": {"name": "debug", "namespace": "default"},
+                "spec": {"containers": [
+                    {"name": "shell", "image": "busybox", "securityContext": {"privileged": true}},
+                ]},
+            },
+            {
+                "kind": "Pod",
+                "metadata": {"name": "web", "namespace": "default"},
+                "spec": {"containers": [{"name": "nginx", "image": "nginx"}]},
+            },
+        ],
+    });
+    let output = privileged_audit(&["-"], &dump.to_string())?;
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "Violations must fail the audit"
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "default\n  privileged: 1 violations\n    Pod debug: Privileged container is not allowed: shell\n1 violations in 2 audited objects\n"
+    );
+
+    let output = privileged_audit(
+        &["--output", "json", &gatekeeper("example_allowed.yaml")],
+        "",
+    )?;
+    assert_eq!(output.status.code(), Some(0));
+    let report: Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(
+        report,
+        json!({"auditedObjects": 1, "totalViolations": 0, "namespaces": {}})
+    );
+    Ok(())
+}
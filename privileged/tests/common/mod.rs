@@ -0,0 +1,426 @@
+//! A WebAssembly host running the policy module under wasmtime, the same way
+//! `host.go` runs it under wazero: the object to test, the parameters and the
+//! optional inventory are passed as program arguments, the outcome is read
+//! from stdout and the `env.log` import collects the log records, which can
+//! be made more verbose through the `set_log_level` export. The `env.now`
+//! import returns the system time, or a fixed time to test time-dependent
+//! rules, and the `env.external_data` import answers from [`external_data`],
+//! a stub of the external data providers.
+//!
+//! The module is built from the workspace on first use, so the tests always
+//! run the current code; set `POLICY_WASM` to test a prebuilt module instead.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use policy_sdk::metadata::{append_custom_section, CUSTOM_SECTION};
+use policy_sdk::Policy;
+use privileged::Privileged;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use wasmtime::{Caller, Engine, Extern, Instance, Linker, Memory, Module, Store, Val};
+use wasmtime_wasi::pipe::MemoryOutputPipe;
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::WasiCtxBuilder;
+
+/// Capacity of the captured stdout.
+const STDOUT_CAPACITY: usize = 1 << 20;
+
+/// Returns the policy module, building it on first use.
+pub fn policy_wasm() -> Result<&'static [u8]> {
+    static WASM: OnceLock<Result<Vec<u8>, String>> = OnceLock::new();
+    WASM.get_or_init(|| load_policy_wasm().map_err(|e| format!("{:#}", e)))
+        .as_deref()
+        .map_err(|e| anyhow!("{}", e))
+}
+
+fn load_policy_wasm() -> Result<Vec<u8>> {
+    if let Ok(path) = env::var("POLICY_WASM") {
+        return fs::read(&path).with_context(|| format!("reading {}", path));
+    }
+
+    // Build into a target directory of its own, so the build doesn't wait for
+    // the lock cargo holds on the one running the tests.
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = manifest_dir.join("../target/wasm-tests");
+    let status = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+        .current_dir(manifest_dir)
+        .args(["build", "--release", "--lib", "--package", "privileged"])
+        .args(["--target", "wasm32-wasip1", "--target-dir"])
+        .arg(&target_dir)
+        .status()
+        .context("running cargo")?;
+    if !status.success() {
+        return Err(anyhow!(
+            "building policy.wasm failed, is the target installed? Run `rustup target add wasm32-wasip1`"
+        ));
+    }
+
+    // Same as `make`, which embeds the metadata with the embed-metadata tool.
+    let mut wasm = fs::read(target_dir.join("wasm32-wasip1/release/privileged.wasm"))?;
+    append_custom_section(
+        &mut wasm,
+        CUSTOM_SECTION,
+        Privileged::metadata().to_json().as_bytes(),
+    );
+    Ok(wasm)
+}
+
+/// State of a single evaluation.
+struct HostState {
+    wasi: WasiP1Ctx,
+    logs: Vec<Value>,
+    /// What the `env.now` import returns, in seconds since the Unix epoch.
+    now: i64,
+}
+
+/// The current time, in seconds since the Unix epoch.
+pub fn system_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time after the epoch")
+        .as_secs() as i64
+}
+
+/// What the module reported for an evaluation.
+#[derive(Debug)]
+pub struct Evaluation {
+    /// Everything printed to stdout, without the trailing newline.
+    pub stdout: String,
+    /// The records passed to the `env.log` import.
+    pub logs: Vec<Value>,
+}
+
+/// What the `validate` export returned for an evaluation.
+#[derive(Debug, Deserialize)]
+pub struct Review {
+    pub allowed: bool,
+    pub violations: Vec<Violation>,
+    #[serde(default)]
+    pub exemptions: Vec<Exemption>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    pub results: Vec<ObjectResult>,
+    pub error: Option<String>,
+    /// The records passed to the `env.log` import.
+    #[serde(skip)]
+    pub logs: Vec<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Violation {
+    pub message: String,
+    pub rule: Option<String>,
+    pub enforcement_action: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Exemption {
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ObjectResult {
+    pub key: String,
+    pub allowed: bool,
+    pub violations: Vec<Violation>,
+    pub trace: Option<Value>,
+}
+
+/// What the `mutate` export returned.
+#[derive(Debug, Deserialize)]
+pub struct Mutation {
+    /// The JSONPatch operations.
+    pub patch: Vec<serde_json::Value>,
+    pub error: Option<String>,
+}
+
+pub struct PolicyHost {
+    engine: Engine,
+    module: Module,
+}
+
+impl PolicyHost {
+    /// Returns a host for the policy module, see [`policy_wasm`]. The module
+    /// is compiled once and shared by the tests.
+    pub fn shared() -> Result<&'static Self> {
+        static HOST: OnceLock<Result<PolicyHost, String>> = OnceLock::new();
+        HOST.get_or_init(|| PolicyHost::compile().map_err(|e| format!("{:#}", e)))
+            .as_ref()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    fn compile() -> Result<Self> {
+        let engine = Engine::default();
+        let module = Module::new(&engine, policy_wasm()?)?;
+        Ok(PolicyHost { engine, module })
+    }
+
+    /// Calls the `eval` export with the given object and parameters.
+    pub fn eval(&self, object: &str, parameters: &str) -> Result<Evaluation> {
+        self.eval_args(&[object, parameters])
+    }
+
+    /// Calls the `eval` export with the given object, parameters and
+    /// inventory.
+    pub fn eval_with_inventory(
+        &self,
+        object: &str,
+        parameters: &str,
+        inventory: &str,
+    ) -> Result<Evaluation> {
+        self.eval_args(&[object, parameters, inventory])
+    }
+
+    fn eval_args(&self, args: &[&str]) -> Result<Evaluation> {
+        let stdout = MemoryOutputPipe::new(STDOUT_CAPACITY);
+        let wasi = WasiCtxBuilder::new()
+            .arg("wasi")
+            .args(args)
+            .stdout(stdout.clone())
+            .inherit_stderr()
+            .build_p1();
+        let (mut store, instance) = self.instantiate(wasi, system_time())?;
+
+        instance
+            .get_typed_func::<(), ()>(&mut store, "eval")?
+            .call(&mut store, ())?;
+
+        let stdout = String::from_utf8(stdout.contents().to_vec())?;
+        Ok(Evaluation {
+            stdout: stdout.trim_end_matches('\n').to_string(),
+            logs: store.into_data().logs,
+        })
+    }
+
+    /// Calls the `validate` export with the given object and parameters.
+    pub fn validate(&self, object: &str, parameters: &str) -> Result<Review> {
+        self.validate_at(object, parameters, system_time())
+    }
+
+    /// Calls the `validate` export as if the current time were `now`, in
+    /// seconds since the Unix epoch.
+    pub fn validate_at(&self, object: &str, parameters: &str, now: i64) -> Result<Review> {
+        self.review("validate", &[object, parameters], now, None)
+    }
+
+    /// Calls the `validate` export after setting the log level through the
+    /// `set_log_level` export, e.g. 3 for `debug`.
+    pub fn validate_with_log_level(
+        &self,
+        object: &str,
+        parameters: &str,
+        log_level: u32,
+    ) -> Result<Review> {
+        self.review(
+            "validate",
+            &[object, parameters],
+            system_time(),
+            Some(log_level),
+        )
+    }
+
+    /// Calls the `validate_with_inventory` export with the given object,
+    /// parameters and inventory.
+    pub fn validate_with_inventory(
+        &self,
+        object: &str,
+        parameters: &str,
+        inventory: &str,
+    ) -> Result<Review> {
+        self.review(
+            "validate_with_inventory",
+            &[object, parameters, inventory],
+            system_time(),
+            None,
+        )
+    }
+
+    fn review(
+        &self,
+        name: &str,
+        inputs: &[&str],
+        now: i64,
+        log_level: Option<u32>,
+    ) -> Result<Review> {
+        let (response, logs) = self.call_with_buffers(name, inputs, now, log_level)?;
+        let mut review: Review = serde_json::from_str(&response)?;
+        review.logs = logs;
+        Ok(review)
+    }
+
+    /// Calls the `mutate` export with the given object and parameters.
+    pub fn mutate(&self, object: &str, parameters: &str) -> Result<Mutation> {
+        let (response, _) =
+            self.call_with_buffers("mutate", &[object, parameters], system_time(), None)?;
+        Ok(serde_json::from_str(&response)?)
+    }
+
+    /// Calls an export taking its inputs in buffers, e.g. the object and the
+    /// parameters, and returns its result along with the logs.
+    fn call_with_buffers(
+        &self,
+        name: &str,
+        inputs: &[&str],
+        now: i64,
+        log_level: Option<u32>,
+    ) -> Result<(String, Vec<Value>)> {
+        let (mut store, instance) = self.instantiate(WasiCtxBuilder::new().build_p1(), now)?;
+        if let Some(log_level) = log_level {
+            instance
+                .get_typed_func::<u32, ()>(&mut store, "set_log_level")?
+                .call(&mut store, log_level)?;
+        }
+
+        let mut params = Vec::new();
+        for input in inputs {
+            let (ptr, size) = write_string(&mut store, &instance, input)?;
+            params.extend([Val::I32(ptr as i32), Val::I32(size as i32)]);
+        }
+        let mut results = [Val::I64(0)];
+        instance
+            .get_func(&mut store, name)
+            .ok_or_else(|| anyhow!("the module does not export {}", name))?
+            .call(&mut store, &params, &mut results)?;
+        let packed = results[0]
+            .i64()
+            .ok_or_else(|| anyhow!("{} returned {:?}", name, results[0]))?;
+
+        let response = take_string(&mut store, &instance, packed as u64)?;
+        Ok((response, store.into_data().logs))
+    }
+
+    /// Calls the `metadata` export, reading and releasing the returned string.
+    pub fn metadata(&self) -> Result<String> {
+        let (mut store, instance) =
+            self.instantiate(WasiCtxBuilder::new().build_p1(), system_time())?;
+
+        let packed = instance
+            .get_typed_func::<(), u64>(&mut store, "metadata")?
+            .call(&mut store, ())?;
+        take_string(&mut store, &instance, packed)
+    }
+
+    fn instantiate(&self, wasi: WasiP1Ctx, now: i64) -> Result<(Store<HostState>, Instance)> {
+        let mut linker = Linker::new(&self.engine);
+        preview1::add_to_linker_sync(&mut linker, |state: &mut HostState| &mut state.wasi)?;
+        linker.func_wrap(
+            "env",
+            "log",
+            |mut caller: Caller<'_, HostState>, ptr: u32, len: u32| -> Result<()> {
+                let record = serde_json::from_str(&read_string(&mut caller, ptr, len)?)?;
+                caller.data_mut().logs.push(record);
+                Ok(())
+            },
+        )?;
+        linker.func_wrap("env", "now", |caller: Caller<'_, HostState>| -> i64 {
+            caller.data().now
+        })?;
+        linker.func_wrap(
+            "env",
+            "external_data",
+            |mut caller: Caller<'_, HostState>, ptr: u32, len: u32| -> Result<u64> {
+                let request = read_string(&mut caller, ptr, len)?;
+                let response = external_data(&serde_json::from_str(&request)?).to_string();
+                let allocate = match caller.get_export("allocate") {
+                    Some(Extern::Func(allocate)) => allocate.typed::<u32, u32>(&caller)?,
+                    _ => return Err(anyhow!("the module does not export allocate")),
+                };
+                let ptr = allocate.call(&mut caller, response.len() as u32)?;
+                guest_memory(&mut caller)?.write(&mut caller, ptr as usize, response.as_bytes())?;
+                Ok(((ptr as u64) << 32) | response.len() as u64)
+            },
+        )?;
+
+        let mut store = Store::new(
+            &self.engine,
+            HostState {
+                wasi,
+                logs: Vec::new(),
+                now,
+            },
+        );
+        let instance = linker.instantiate(&mut store, &self.module)?;
+        Ok((store, instance))
+    }
+}
+
+fn memory(store: &mut Store<HostState>, instance: &Instance) -> Result<Memory> {
+    instance
+        .get_memory(store, "memory")
+        .ok_or_else(|| anyhow!("the module does not export its memory"))
+}
+
+/// Copies a string to a buffer from the `allocate` export, returning the
+/// buffer (linear memory offset, byteCount).
+fn write_string(store: &mut Store<HostState>, instance: &Instance, s: &str) -> Result<(u32, u32)> {
+    let len = s.len() as u32;
+    let ptr = instance
+        .get_typed_func::<u32, u32>(&mut *store, "allocate")?
+        .call(&mut *store, len)?;
+    memory(store, instance)?.write(&mut *store, ptr as usize, s.as_bytes())?;
+    Ok((ptr, len))
+}
+
+/// Reads a string returned by the module, packed as the linear memory offset
+/// in the upper 32 bits and the byteCount in the lower ones, and releases it.
+fn take_string(store: &mut Store<HostState>, instance: &Instance, packed: u64) -> Result<String> {
+    let (ptr, len) = ((packed >> 32) as u32, packed as u32);
+    let mut buf = vec![0; len as usize];
+    memory(store, instance)?.read(&mut *store, ptr as usize, &mut buf)?;
+
+    instance
+        .get_typed_func::<(u32, u32), ()>(&mut *store, "deallocate")?
+        .call(&mut *store, (ptr, len))?;
+    Ok(String::from_utf8(buf)?)
+}
+
+fn guest_memory(caller: &mut Caller<'_, HostState>) -> Result<Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(anyhow!("the module does not export its memory")),
+    }
+}
+
+/// Reads a string (linear memory offset, byteCount) from the guest memory.
+fn read_string(caller: &mut Caller<'_, HostState>, ptr: u32, len: u32) -> Result<String> {
+    let memory = guest_memory(caller)?;
+    let mut buf = vec![0; len as usize];
+    memory
+        .read(&caller, ptr as usize, &mut buf)
+        .map_err(|_| anyhow!("memory read({}, {}) out of range", ptr, len))?;
+    Ok(String::from_utf8(buf)?)
+}
+
+/// The external data providers of the test host. `namespace-labels` returns
+/// the labels of the `kube-system` and `prod` namespaces.
+pub fn external_data(request: &Value) -> Value {
+    if request["provider"] != "namespace-labels" {
+        return json!({"systemError": format!("unknown provider {}", request["provider"])});
+    }
+    let items: Vec<Value> = request["keys"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|key| match key.as_str() {
+            Some("kube-system") => json!({
+                "key": key,
+                "value": {"pod-security.kubernetes.io/enforce": "privileged"},
+            }),
+            Some("prod") => json!({
+                "key": key,
+                "value": {"pod-security.kubernetes.io/enforce": "restricted", "team": "web"},
+            }),
+            _ => json!({"key": key, "error": "namespace not found"}),
+        })
+        .collect();
+    json!({ "items": items })
+}
@@ -0,0 +1,159 @@
+//! Runs the policy against every object under `gatekeeper/`: the examples
+//! shipped with the constraint and the cases under `gatekeeper/tests/`.
+//!
+//! A fixture states the number of violations it expects with the
+//! `wasm-policies.test/expected-violations` annotation, and may override the
+//! parameters of `constraint.yaml` with `wasm-policies.test/parameters`. The
+//! examples carry no annotation, their file name tells whether they are
+//! allowed.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use policy_sdk::{parse_parameters, EnforcementAction, Policy, Request};
+use privileged::{template, Parameters, Privileged};
+use serde::Deserialize;
+use serde_json::Value;
+
+const EXPECTED_VIOLATIONS: &str = "wasm-policies.test/expected-violations";
+const PARAMETERS: &str = "wasm-policies.test/parameters";
+
+/// What a fixture expects from the policy.
+#[derive(Debug, PartialEq, Eq)]
+enum Expected {
+    /// The number of violations denying the object.
+    Violations(usize),
+    Denied,
+}
+
+struct Fixture {
+    /// File and name of the object, to report failures.
+    source: String,
+    object: Value,
+    params: Parameters,
+    expected: Expected,
+}
+
+fn gatekeeper_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("gatekeeper")
+}
+
+/// Lists the YAML files under `dir` and its subdirectories.
+fn yaml_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(yaml_files(&path)?);
+        } else if path.extension().is_some_and(|ext| ext == "yaml") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn read_documents(path: &Path) -> Result<Vec<Value>> {
+    let content = fs::read_to_string(path)?;
+    serde_yaml::Deserializer::from_str(&content)
+        .map(|document| Ok(Value::deserialize(document)?))
+        .filter(|document| !matches!(document, Ok(Value::Null)))
+        .collect()
+}
+
+/// The parameters of the constraint shipped with the policy.
+fn constraint_parameters() -> Result<Value> {
+    let constraint = read_documents(&gatekeeper_dir().join("constraint.yaml"))?;
+    Ok(constraint
+        .first()
+        .and_then(|c| c.pointer("/spec/parameters"))
+        .cloned()
+        .unwrap_or(Value::Null))
+}
+
+fn load_fixtures() -> Result<Vec<Fixture>> {
+    let defaults = constraint_parameters()?;
+    let mut fixtures = Vec::new();
+    for path in yaml_files(&gatekeeper_dir())? {
+        let file = path.strip_prefix(gatekeeper_dir())?.display().to_string();
+        for object in read_documents(&path).with_context(|| format!("reading {}", file))? {
+            // The template, the constraint and the suite live next to the examples.
+            let kind = object["kind"].as_str().unwrap_or_default();
+            if ["ConstraintTemplate", "Suite", template::KIND].contains(&kind) {
+                continue;
+            }
+            let source = format!("{} ({})", file, object["metadata"]["name"]);
+            let annotations = &object["metadata"]["annotations"];
+
+            let params = match annotations[PARAMETERS].as_str() {
+                Some(params) => params.to_string(),
+                None => defaults.to_string(),
+            };
+            let params = parse_parameters::<Parameters>(&params)
+                .map_err(|e| anyhow!("{}: {}", source, e))?;
+
+            let expected = match annotations[EXPECTED_VIOLATIONS].as_str() {
+                Some(count) => Expected::Violations(
+                    count
+                        .parse()
+                        .with_context(|| format!("{}: invalid {}", source, EXPECTED_VIOLATIONS))?,
+                ),
+                None if file.contains("disallowed") => Expected::Denied,
+                None if file.contains("allowed") => Expected::Violations(0),
+                None => return Err(anyhow!("{}: missing {}", source, EXPECTED_VIOLATIONS)),
+            };
+
+            fixtures.push(Fixture {
+                source,
+                object,
+                params,
+                expected,
+            });
+        }
+    }
+    Ok(fixtures)
+}
+
+#[test]
+fn gatekeeper_fixtures_test() -> Result<()> {
+    let fixtures = load_fixtures()?;
+    assert!(
+        fixtures
+            .iter()
+            .any(|f| f.source.starts_with("example_allowed"))
+            && fixtures
+                .iter()
+                .any(|f| f.source.starts_with("example_disallowed")),
+        "The examples must be part of the fixtures"
+    );
+
+    let mut failures = Vec::new();
+    for fixture in &fixtures {
+        let decision = Privileged::validate(&Request::new(fixture.object.clone()), &fixture.params);
+        let passed = match fixture.expected {
+            Expected::Violations(count) => {
+                let denying = decision
+                    .violations
+                    .iter()
+                    .filter(|v| v.enforcement_action == EnforcementAction::Deny);
+                denying.count() == count
+            }
+            Expected::Denied => !decision.is_allowed(),
+        };
+        if !passed {
+            failures.push(format!(
+                "{}: expected {:?}, got {:?}",
+                fixture.source, fixture.expected, decision.violations
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} fixtures failed:\n{}",
+        failures.len(),
+        fixtures.len(),
+        failures.join("\n")
+    );
+    Ok(())
+}
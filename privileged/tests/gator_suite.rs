@@ -0,0 +1,179 @@
+//! Runs Gatekeeper test suites, in the `Suite` format of `gator verify`,
+//! against the policy module: each case is evaluated by the `validate`
+//! export with the parameters of the constraint of its test, and the
+//! violations are checked against the assertions of the case.
+//!
+//! The suite is `gatekeeper/suite.yaml`; point `GATOR_SUITE` to other suites
+//! (separated by `:`), e.g. the ones of the Gatekeeper library, to run them
+//! against the Rust implementation. As there is no Rego engine here, the
+//! template of a test is only checked to define the kind of the constraint,
+//! and the `message` of an assertion is matched as a plain substring rather
+//! than a regular expression.
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use common::{PolicyHost, Review};
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Deserialize)]
+struct Suite {
+    kind: String,
+    #[serde(default)]
+    tests: Vec<Test>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Test {
+    name: String,
+    template: String,
+    constraint: String,
+    #[serde(default)]
+    cases: Vec<Case>,
+    #[serde(default)]
+    skip: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Case {
+    name: String,
+    object: String,
+    #[serde(default)]
+    assertions: Vec<Assertion>,
+    #[serde(default)]
+    skip: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Assertion {
+    /// `yes`, `no` or the exact number of violations, `yes` when unset.
+    violations: Option<Violations>,
+    /// Only count the violations whose message contains this.
+    message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Violations {
+    Count(usize),
+    // `yes` or `no`, which YAML 1.2 reads as strings.
+    Word(String),
+}
+
+impl Assertion {
+    fn check(&self, review: &Review) -> Result<()> {
+        let matching = review
+            .violations
+            .iter()
+            .filter(|v| {
+                self.message
+                    .as_deref()
+                    .is_none_or(|m| v.message.contains(m))
+            })
+            .count();
+        let passed = match &self.violations {
+            None => matching > 0,
+            Some(Violations::Count(count)) => matching == *count,
+            Some(Violations::Word(word)) => match word.as_str() {
+                "yes" => matching > 0,
+                "no" => matching == 0,
+                _ => return Err(anyhow!("invalid assertion `violations: {}`", word)),
+            },
+        };
+        if passed {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "expected {} violations{}, got {:?}",
+                match &self.violations {
+                    None => "some".to_string(),
+                    Some(Violations::Count(count)) => count.to_string(),
+                    Some(Violations::Word(word)) => word.clone(),
+                },
+                match &self.message {
+                    Some(message) => format!(" matching {:?}", message),
+                    None => String::new(),
+                },
+                review.violations
+            ))
+        }
+    }
+}
+
+fn read_object(path: &Path) -> Result<Value> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    serde_yaml::from_str(&content).with_context(|| format!("parsing {}", path.display()))
+}
+
+/// Runs a suite, returning the failures.
+fn run_suite(host: &PolicyHost, path: &Path) -> Result<Vec<String>> {
+    let suite: Suite = serde_yaml::from_str(&fs::read_to_string(path)?)
+        .with_context(|| format!("parsing {}", path.display()))?;
+    if suite.kind != "Suite" {
+        return Err(anyhow!("{} is not a Suite", path.display()));
+    }
+    // Paths are relative to the suite.
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut failures = Vec::new();
+    for test in suite.tests.iter().filter(|t| !t.skip) {
+        let template = read_object(&dir.join(&test.template))?;
+        let constraint = read_object(&dir.join(&test.constraint))?;
+        let constraint_kind = &constraint["kind"];
+        if &template["spec"]["crd"]["spec"]["names"]["kind"] != constraint_kind {
+            return Err(anyhow!(
+                "{}: the template does not define the constraint kind {}",
+                test.name,
+                constraint_kind
+            ));
+        }
+        let parameters = constraint
+            .pointer("/spec/parameters")
+            .map(Value::to_string)
+            .unwrap_or_default();
+
+        for case in test.cases.iter().filter(|c| !c.skip) {
+            let object = read_object(&dir.join(&case.object))?;
+            let review = host.validate(&object.to_string(), &parameters)?;
+            if let Some(error) = &review.error {
+                failures.push(format!("{}/{}: {}", test.name, case.name, error));
+                continue;
+            }
+            for assertion in &case.assertions {
+                if let Err(e) = assertion.check(&review) {
+                    failures.push(format!("{}/{}: {}", test.name, case.name, e));
+                }
+            }
+        }
+    }
+    Ok(failures)
+}
+
+fn suites() -> Vec<PathBuf> {
+    match env::var("GATOR_SUITE") {
+        Ok(paths) => env::split_paths(&paths).collect(),
+        Err(_) => vec![Path::new(env!("CARGO_MANIFEST_DIR")).join("gatekeeper/suite.yaml")],
+    }
+}
+
+#[test]
+fn gator_suite_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let mut failures = Vec::new();
+    for suite in suites() {
+        failures.extend(run_suite(host, &suite)?);
+    }
+    assert!(
+        failures.is_empty(),
+        "{} cases failed:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+    Ok(())
+}
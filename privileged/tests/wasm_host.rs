@@ -0,0 +1,391 @@
+mod common;
+
+use anyhow::Result;
+use common::PolicyHost;
+use policy_sdk::metadata::{custom_section, CUSTOM_SECTION};
+use policy_sdk::Policy;
+use privileged::Privileged;
+use serde_json::{json, Value};
+
+const PRIVILEGED_POD: &str = r#"{"apiVersion":"v1","kind":"Pod","metadata":{"name":"nginx","labels":{"app":"nginx"}},"spec":{"containers":[{"name":"nginx","image":"nginx","securityContext":{"privileged":true}}]}}"#;
+
+const UNPRIVILEGED_POD: &str = r#"{"apiVersion":"v1","kind":"Pod","metadata":{"name":"nginx","labels":{"app":"nginx"}},"spec":{"containers":[{"name":"nginx","image":"nginx","securityContext":{"privileged":false}}]}}"#;
+
+#[test]
+fn deny_privileged_pod_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    assert_eq!(host.eval(PRIVILEGED_POD, "")?.stdout, "false");
+    Ok(())
+}
+
+#[test]
+fn allow_unprivileged_pod_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    assert_eq!(host.eval(UNPRIVILEGED_POD, "")?.stdout, "true");
+    Ok(())
+}
+
+#[test]
+fn allow_exempt_image_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let evaluation = host.eval(PRIVILEGED_POD, r#"{"exemptImages":["nginx"]}"#)?;
+    assert_eq!(evaluation.stdout, "true");
+    Ok(())
+}
+
+#[test]
+fn report_invalid_parameters_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let evaluation = host.eval(PRIVILEGED_POD, r#"{"exemptImage":["nginx"]}"#)?;
+    assert!(
+        evaluation
+            .stdout
+            .starts_with("error: invalid parameters: unknown field `exemptImage`"),
+        "unexpected output: {}",
+        evaluation.stdout
+    );
+    assert_eq!(evaluation.logs.len(), 1);
+    assert_eq!(evaluation.logs[0]["level"], "error");
+    Ok(())
+}
+
+#[test]
+fn log_dryrun_violations_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let evaluation = host.eval(
+        PRIVILEGED_POD,
+        r#"{"rules":{"privileged":{"enforcementAction":"dryrun"}}}"#,
+    )?;
+    assert_eq!(evaluation.stdout, "true");
+    assert_eq!(
+        evaluation.logs,
+        vec![json!({
+            "level": "info",
+            "msg": "dryrun violation",
+            "rule": "privileged",
+            "violation": "Privileged container is not allowed: nginx",
+        })]
+    );
+    Ok(())
+}
+
+#[test]
+fn metadata_export_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let metadata = host.metadata()?;
+    assert_eq!(metadata, Privileged::metadata().to_json());
+    let document: Value = serde_json::from_str(&metadata)?;
+    assert_eq!(
+        document["kinds"],
+        json!([
+            {"apiGroups": [""], "kinds": ["Pod", "PodTemplate", "ReplicationController"]},
+            {"apiGroups": ["apps"], "kinds": ["Deployment", "StatefulSet", "DaemonSet", "ReplicaSet"]},
+            {"apiGroups": ["batch"], "kinds": ["Job", "CronJob"]},
+        ]),
+        "The kinds must cover the workloads the policy validates"
+    );
+    assert_eq!(
+        custom_section(common::policy_wasm()?, CUSTOM_SECTION),
+        Some(metadata.as_bytes()),
+        "The metadata export and the custom section must agree"
+    );
+    Ok(())
+}
+
+#[test]
+fn validate_export_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let review = host.validate(PRIVILEGED_POD, "")?;
+    assert!(!review.allowed);
+    let messages: Vec<_> = review.violations.iter().map(|v| &v.message).collect();
+    assert_eq!(messages, vec!["Privileged container is not allowed: nginx"]);
+
+    let review = host.validate(UNPRIVILEGED_POD, "")?;
+    assert!(review.allowed);
+    assert!(review.violations.is_empty());
+
+    let review = host.validate(PRIVILEGED_POD, r#"{"exemptImage":["nginx"]}"#)?;
+    assert!(
+        !review.allowed,
+        "Invalid parameters must not allow the request"
+    );
+    assert!(review.error.is_some(), "The error must be reported");
+    Ok(())
+}
+
+const PODS_YAML: &str = r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: web
+  namespace: prod
+spec:
+  containers:
+    - name: nginx
+      image: nginx
+---
+apiVersion: v1
+kind: PodList
+items:
+  - apiVersion: v1
+    kind: Pod
+    metadata:
+      name: debug
+      namespace: prod
+    spec:
+      containers:
+        - name: shell
+          image: busybox
+          securityContext:
+            privileged: true
+"#;
+
+#[test]
+fn eval_yaml_documents_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let evaluation = host.eval(PODS_YAML, "")?;
+    assert_eq!(evaluation.stdout, "false");
+    assert_eq!(
+        evaluation.logs,
+        vec![json!({
+            "level": "info",
+            "msg": "violation",
+            "object": "Pod/prod/debug",
+            "violation": "Privileged container is not allowed: shell",
+        })]
+    );
+    Ok(())
+}
+
+#[test]
+fn validate_yaml_documents_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let review = host.validate(PODS_YAML, "")?;
+    assert!(!review.allowed);
+    let results: Vec<_> = review
+        .results
+        .iter()
+        .map(|r| (r.key.as_str(), r.allowed, r.violations.len()))
+        .collect();
+    assert_eq!(
+        results,
+        vec![("Pod/prod/web", true, 0), ("Pod/prod/debug", false, 1)]
+    );
+    Ok(())
+}
+
+#[test]
+fn mutate_export_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let mutation = host.mutate(PRIVILEGED_POD, "")?;
+    assert_eq!(mutation.error, None);
+    assert_eq!(
+        mutation.patch,
+        vec![serde_json::json!({
+            "op": "replace",
+            "path": "/spec/containers/0/securityContext/privileged",
+            "value": false,
+        })]
+    );
+
+    let mutation = host.mutate(UNPRIVILEGED_POD, "")?;
+    assert!(mutation.patch.is_empty(), "A valid pod must not be changed");
+    Ok(())
+}
+
+#[test]
+fn exemption_expires_at_host_time_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let pod = r#"{"kind":"Pod","metadata":{"name":"nginx","annotations":{"example.com/exempt":"nginx=OPS-123;expires=2026-12-31"}},"spec":{"containers":[{"name":"nginx","image":"nginx","securityContext":{"privileged":true}}]}}"#;
+    let parameters = r#"{"exemptionAnnotation":"example.com/exempt"}"#;
+    // 2027-01-01T00:00:00Z
+    let new_year = 1798761600;
+
+    let review = host.validate_at(pod, parameters, new_year - 1)?;
+    assert!(review.allowed, "The expiry day must still be exempted");
+    let exemptions: Vec<_> = review.exemptions.iter().map(|e| &e.message).collect();
+    assert_eq!(
+        exemptions,
+        vec!["Container nginx is exempted until 2026-12-31: OPS-123"]
+    );
+
+    let review = host.validate_at(pod, parameters, new_year)?;
+    assert!(!review.allowed);
+    assert!(review.exemptions.is_empty());
+    assert_eq!(
+        review.logs,
+        vec![json!({
+            "level": "info",
+            "msg": "exemption expired",
+            "container": "nginx",
+            "expires": "2026-12-31",
+        })]
+    );
+    Ok(())
+}
+
+#[test]
+fn exempt_namespace_labels_from_external_data_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let pod = |namespace: &str| {
+        PRIVILEGED_POD.replace(
+            r#""name":"nginx","#,
+            &format!(r#""name":"nginx","namespace":"{}","#, namespace),
+        )
+    };
+    let parameters = |provider: &str| {
+        format!(
+            r#"{{"namespaceLabels":{{"provider":"{}","exempt":{{"pod-security.kubernetes.io/enforce":"privileged"}}}}}}"#,
+            provider
+        )
+    };
+
+    let review = host.validate(&pod("kube-system"), &parameters("namespace-labels"))?;
+    assert!(review.allowed, "{:?}", review);
+    let review = host.validate(&pod("prod"), &parameters("namespace-labels"))?;
+    assert!(!review.allowed);
+
+    let review = host.validate(&pod("staging"), &parameters("namespace-labels"))?;
+    assert!(!review.allowed, "An unknown namespace must not be exempted");
+    assert_eq!(
+        review.logs,
+        vec![json!({
+            "level": "warn",
+            "msg": "namespace labels lookup failed",
+            "namespace": "staging",
+            "error": "external data for staging: namespace not found",
+        })]
+    );
+
+    let review = host.validate(&pod("kube-system"), &parameters("labels"))?;
+    assert!(!review.allowed, "A failed lookup must not exempt");
+    assert_eq!(
+        review.logs[0]["error"],
+        r#"provider labels: unknown provider "labels""#
+    );
+    Ok(())
+}
+
+#[test]
+fn exempt_namespace_labels_from_inventory_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let pod = PRIVILEGED_POD.replace(r#""name":"nginx","#, r#""name":"nginx","namespace":"ci","#);
+    let parameters = r#"{"namespaceLabels":{"exempt":{"privileged-allowed":"true"}}}"#;
+    let inventory = |labels: &str| {
+        format!(
+            r#"{{"cluster":{{"v1":{{"Namespace":{{"ci":{{"apiVersion":"v1","kind":"Namespace","metadata":{{"name":"ci","labels":{}}}}}}}}}}}}}"#,
+            labels
+        )
+    };
+
+    let review = host.validate_with_inventory(
+        &pod,
+        parameters,
+        &inventory(r#"{"privileged-allowed":"true"}"#),
+    )?;
+    assert!(review.allowed, "{:?}", review);
+    let review = host.validate_with_inventory(&pod, parameters, &inventory("{}"))?;
+    assert!(!review.allowed);
+    assert!(
+        !host.validate(&pod, parameters)?.allowed,
+        "Nothing must be exempted without an inventory"
+    );
+
+    let evaluation = host.eval_with_inventory(
+        &pod,
+        parameters,
+        &inventory(r#"{"privileged-allowed":"true"}"#),
+    )?;
+    assert_eq!(evaluation.stdout, "true");
+    let evaluation = host.eval_with_inventory(&pod, parameters, "[")?;
+    assert!(
+        evaluation.stdout.starts_with("error: invalid inventory"),
+        "{}",
+        evaluation.stdout
+    );
+    Ok(())
+}
+
+#[test]
+fn debug_logs_follow_host_level_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let parameters = r#"{"rules":{"hostNamespaces":{"enabled":true}}}"#;
+    let pod = PRIVILEGED_POD.replace(r#""spec":{"#, r#""spec":{"hostPID":true,"#);
+
+    let review = host.validate(&pod, parameters)?;
+    assert!(review.logs.is_empty(), "Debug logs must be off by default");
+
+    let review = host.validate_with_log_level(&pod, parameters, 3)?;
+    assert_eq!(
+        review.logs,
+        vec![
+            json!({
+                "level": "debug",
+                "msg": "rule matched",
+                "rule": "privileged",
+                "container": "nginx",
+                "action": "deny",
+            }),
+            json!({
+                "level": "debug",
+                "msg": "rule matched",
+                "rule": "hostNamespaces",
+                "container": null,
+                "action": "deny",
+            }),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn explain_decision_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let review = host.validate(PRIVILEGED_POD, r#"{"explain":true}"#)?;
+    let trace = review.results[0].trace.as_ref().expect("a trace");
+    assert_eq!(trace["outcome"], "denied");
+    assert_eq!(
+        trace["containers"][0]["effectiveSecurityContext"],
+        json!({"privileged": true})
+    );
+    assert_eq!(trace["rules"][0]["rule"], "privileged");
+    assert!(
+        review.logs.is_empty(),
+        "The trace must be in the result, not the log"
+    );
+
+    let review = host.validate(PRIVILEGED_POD, "")?;
+    assert_eq!(review.results[0].trace, None);
+    Ok(())
+}
+
+#[test]
+fn warn_enforcement_action_test() -> Result<()> {
+    let host = PolicyHost::shared()?;
+    let parameters = r#"{"rules":{"privileged":{"enforcementAction":"warn"}}}"#;
+
+    let review = host.validate(PRIVILEGED_POD, parameters)?;
+    assert!(review.allowed);
+    assert_eq!(
+        review.warnings,
+        vec!["Privileged container is not allowed: nginx"]
+    );
+    assert_eq!(review.violations[0].enforcement_action, "warn");
+
+    let evaluation = host.eval(PRIVILEGED_POD, parameters)?;
+    assert_eq!(evaluation.stdout, "true");
+    assert_eq!(
+        evaluation.logs,
+        vec![json!({
+            "level": "warn",
+            "msg": "warning",
+            "object": "Pod/nginx",
+            "warning": "Privileged container is not allowed: nginx",
+        })]
+    );
+    assert!(
+        host.mutate(PRIVILEGED_POD, parameters)?.patch.is_empty(),
+        "Violations in warn mode must not be fixed"
+    );
+    Ok(())
+}
@@ -0,0 +1,157 @@
+//! Procedural macros of the policy SDK. Use them through the `policy-sdk`
+//! crate, which re-exports them.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Error, ItemImpl};
+
+/// Generates the WebAssembly exports of a policy from its `Policy`
+/// implementation:
+///
+/// - `allocate(size) -> ptr` and `deallocate(ptr, size)`, used by the host to
+///   manage strings in linear memory.
+/// - `eval()`, which evaluates the object and parameters passed as program
+///   arguments and prints the outcome.
+/// - `validate(object_ptr, object_size, parameters_ptr, parameters_size) ->
+///   ptr/len`, which evaluates an object and parameters the host wrote to
+///   buffers from `allocate`, and returns the violations as JSON.
+/// - `validate_with_inventory(object_ptr, object_size, parameters_ptr,
+///   parameters_size, inventory_ptr, inventory_size) -> ptr/len`, same as
+///   `validate` with the objects of the cluster in a third buffer.
+/// - `mutate(object_ptr, object_size, parameters_ptr, parameters_size) ->
+///   ptr/len`, same as `validate` but returning the JSONPatch from
+///   `Policy::mutate`.
+/// - `metadata() -> ptr/len`, returning the policy metadata as JSON.
+/// - `set_log_level(level)`, which the host may call first to choose the
+///   most verbose level logged, from 0 for `error` to 3 for `debug`.
+///
+/// The exports are only generated when compiling to WebAssembly, so the same
+/// crate can be used natively, e.g. by tests and command-line tools.
+#[proc_macro_attribute]
+pub fn policy(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return Error::new(
+            proc_macro2::Span::call_site(),
+            "#[policy] does not take arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let item = parse_macro_input!(item as ItemImpl);
+    let is_policy_impl = item
+        .trait_
+        .as_ref()
+        .and_then(|(_, path, _)| path.segments.last())
+        .map(|segment| segment.ident == "Policy")
+        .unwrap_or(false);
+    if !is_policy_impl {
+        return Error::new_spanned(
+            &item.self_ty,
+            "#[policy] must be put on `impl Policy for ...`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let policy = &item.self_ty;
+    let exports = quote! {
+        #[cfg(target_arch = "wasm32")]
+        const _: () = {
+            /// WebAssembly export that allocates a pointer (linear memory
+            /// offset) that can be used for a string.
+            #[export_name = "allocate"]
+            extern "C" fn __policy_allocate(size: u32) -> *mut u8 {
+                ::policy_sdk::runtime::allocate(size as usize)
+            }
+
+            /// WebAssembly export that deallocates a pointer of the given size
+            /// (linear memory offset, byteCount) allocated by `allocate`.
+            #[export_name = "deallocate"]
+            unsafe extern "C" fn __policy_deallocate(ptr: u32, size: u32) {
+                ::policy_sdk::runtime::deallocate(ptr as *mut u8, size as usize)
+            }
+
+            /// WebAssembly export that evaluates the object and parameters
+            /// passed as program arguments.
+            #[export_name = "eval"]
+            extern "C" fn __policy_eval() {
+                ::policy_sdk::runtime::eval::<#policy>()
+            }
+
+            /// WebAssembly export that evaluates the object and parameters
+            /// passed in buffers (linear memory offset, byteCount) allocated
+            /// by `allocate`, and returns the violations as JSON.
+            #[export_name = "validate"]
+            unsafe extern "C" fn __policy_validate(
+                object_ptr: u32,
+                object_size: u32,
+                parameters_ptr: u32,
+                parameters_size: u32,
+            ) -> u64 {
+                ::policy_sdk::runtime::validate::<#policy>(
+                    object_ptr as *mut u8,
+                    object_size as usize,
+                    parameters_ptr as *mut u8,
+                    parameters_size as usize,
+                )
+            }
+
+            /// WebAssembly export that evaluates the object like `validate`,
+            /// with the inventory in a third buffer.
+            #[export_name = "validate_with_inventory"]
+            unsafe extern "C" fn __policy_validate_with_inventory(
+                object_ptr: u32,
+                object_size: u32,
+                parameters_ptr: u32,
+                parameters_size: u32,
+                inventory_ptr: u32,
+                inventory_size: u32,
+            ) -> u64 {
+                ::policy_sdk::runtime::validate_with_inventory::<#policy>(
+                    object_ptr as *mut u8,
+                    object_size as usize,
+                    parameters_ptr as *mut u8,
+                    parameters_size as usize,
+                    inventory_ptr as *mut u8,
+                    inventory_size as usize,
+                )
+            }
+
+            /// WebAssembly export that returns the JSONPatch bringing the
+            /// object passed like for `validate` in line with the policy.
+            #[export_name = "mutate"]
+            unsafe extern "C" fn __policy_mutate(
+                object_ptr: u32,
+                object_size: u32,
+                parameters_ptr: u32,
+                parameters_size: u32,
+            ) -> u64 {
+                ::policy_sdk::runtime::mutate::<#policy>(
+                    object_ptr as *mut u8,
+                    object_size as usize,
+                    parameters_ptr as *mut u8,
+                    parameters_size as usize,
+                )
+            }
+
+            /// WebAssembly export setting the most verbose level logged.
+            #[export_name = "set_log_level"]
+            extern "C" fn __policy_set_log_level(level: u32) {
+                ::policy_sdk::runtime::set_log_level(level)
+            }
+
+            /// WebAssembly export that returns the policy metadata as JSON.
+            #[export_name = "metadata"]
+            extern "C" fn __policy_metadata() -> u64 {
+                ::policy_sdk::runtime::metadata::<#policy>()
+            }
+        };
+    };
+
+    quote! {
+        #item
+        #exports
+    }
+    .into()
+}
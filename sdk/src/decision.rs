@@ -0,0 +1,184 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// What happens when a rule is violated, following the `enforcementAction`
+/// of Gatekeeper constraints.
+///
+/// The variants are documented with plain comments: doc comments would turn
+/// the schema into a `oneOf`, which is not allowed in a structural schema.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EnforcementAction {
+    // The request is denied.
+    #[default]
+    Deny,
+    // The request is allowed, and the violation returned as an admission
+    // warning.
+    Warn,
+    // The violation is only logged, the request is not affected.
+    Dryrun,
+}
+
+/// How serious a violation is, for tools ranking or filtering violations.
+///
+/// The variants are documented with plain comments, as the ones of
+/// [`EnforcementAction`].
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    // The violation gives control of the node, e.g. a privileged container.
+    Critical,
+}
+
+/// Result of validating a request: the request is allowed when there are no
+/// violations to deny it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Decision {
+    pub violations: Vec<Violation>,
+    /// The exemptions that let parts of the object through, so that they
+    /// remain visible.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exemptions: Vec<Exemption>,
+    /// How the decision was reached, when the policy was asked to explain
+    /// it. The content is up to the policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace: Option<Value>,
+}
+
+/// A reason for denying a request, or for warning about it, depending on
+/// its action.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Violation {
+    pub message: String,
+    /// The rule of the policy reporting the violation, for policies made of
+    /// several rules.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    pub enforcement_action: EnforcementAction,
+    /// A stable identifier of the check, e.g. `PSP-PRIV-001`, for tools
+    /// that cannot rely on the message, which may change.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    /// How to fix the object.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remediation: Option<String>,
+    /// A link to the documentation of the check.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documentation_url: Option<String>,
+}
+
+impl Violation {
+    /// Returns a violation denying the request.
+    pub fn new(message: impl Into<String>) -> Self {
+        Violation {
+            message: message.into(),
+            rule: None,
+            enforcement_action: EnforcementAction::Deny,
+            id: None,
+            severity: None,
+            remediation: None,
+            documentation_url: None,
+        }
+    }
+
+    /// Returns the violation with the given action.
+    pub fn with_action(self, enforcement_action: EnforcementAction) -> Self {
+        Violation {
+            enforcement_action,
+            ..self
+        }
+    }
+
+    /// Returns the violation with the given identifier.
+    pub fn with_id(self, id: impl Into<String>) -> Self {
+        Violation {
+            id: Some(id.into()),
+            ..self
+        }
+    }
+
+    /// Returns the violation with the given severity.
+    pub fn with_severity(self, severity: Severity) -> Self {
+        Violation {
+            severity: Some(severity),
+            ..self
+        }
+    }
+
+    /// Returns the violation with a hint on how to fix the object.
+    pub fn with_remediation(self, remediation: impl Into<String>) -> Self {
+        Violation {
+            remediation: Some(remediation.into()),
+            ..self
+        }
+    }
+
+    /// Returns the violation linking to the given documentation.
+    pub fn with_documentation_url(self, url: impl Into<String>) -> Self {
+        Violation {
+            documentation_url: Some(url.into()),
+            ..self
+        }
+    }
+
+    /// Returns the violation attributed to the given rule.
+    pub fn with_rule(self, rule: impl Into<String>) -> Self {
+        Violation {
+            rule: Some(rule.into()),
+            ..self
+        }
+    }
+}
+
+/// An exemption applied while validating the request, e.g. a container
+/// exempted until a given date.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Exemption {
+    pub message: String,
+}
+
+impl Exemption {
+    pub fn new(message: impl Into<String>) -> Self {
+        Exemption {
+            message: message.into(),
+        }
+    }
+}
+
+impl Decision {
+    /// Returns a decision allowing the request.
+    pub fn allow() -> Self {
+        Decision::default()
+    }
+
+    /// Returns a decision denying the request with the given message.
+    pub fn deny(message: impl Into<String>) -> Self {
+        Decision {
+            violations: vec![Violation::new(message)],
+            ..Decision::default()
+        }
+    }
+
+    pub fn is_allowed(&self) -> bool {
+        !self
+            .violations
+            .iter()
+            .any(|violation| violation.enforcement_action == EnforcementAction::Deny)
+    }
+
+    /// The violations to return as admission warnings.
+    pub fn warnings(&self) -> impl Iterator<Item = &Violation> {
+        self.violations
+            .iter()
+            .filter(|violation| violation.enforcement_action == EnforcementAction::Warn)
+    }
+}
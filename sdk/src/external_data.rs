@@ -0,0 +1,100 @@
+//! Data the cluster state doesn't hold, such as the labels of a namespace or
+//! the vulnerabilities of an image, asked to the host the way Gatekeeper asks
+//! its external data providers.
+//!
+//! The module sends `{"provider": "<name>", "keys": [...]}` through the
+//! `env.external_data(ptr, len) -> u64` import. The host answers with
+//! `{"items": [{"key": ..., "value": ..., "error": ...}], "systemError": ...}`
+//! in a buffer from the `allocate` export, packed like the results of the
+//! exports, which the module takes over and frees.
+
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::runtime;
+
+#[derive(Serialize)]
+struct ProviderRequest<'a> {
+    provider: &'a str,
+    keys: &'a [&'a str],
+}
+
+/// The answer of a provider.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ProviderResponse {
+    pub items: Vec<Item>,
+    /// Why the provider could not answer at all.
+    pub system_error: Option<String>,
+}
+
+/// The value of one of the keys, or why the provider has none.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Item {
+    pub key: String,
+    #[serde(default)]
+    pub value: Value,
+    pub error: Option<String>,
+}
+
+impl ProviderResponse {
+    /// Deserializes the value of the key. Returns `None` when the provider
+    /// didn't return the key, and an error when it reported one for it.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let item = match self.items.iter().find(|item| item.key == key) {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        if let Some(error) = &item.error {
+            return Err(anyhow!("external data for {}: {}", key, error));
+        }
+        Ok(Some(T::deserialize(&item.value).map_err(|e| {
+            anyhow!("invalid external data for {}: {}", key, e)
+        })?))
+    }
+}
+
+/// Asks the provider for the values of the keys. Fails when the host has no
+/// such provider, or the provider reports a system error.
+pub fn lookup(provider: &str, keys: &[&str]) -> Result<ProviderResponse> {
+    let request = serde_json::to_string(&ProviderRequest { provider, keys })?;
+    let response = runtime::external_data(&request)?;
+    let response: ProviderResponse = serde_json::from_str(&response)
+        .map_err(|e| anyhow!("invalid response from provider {}: {}", provider, e))?;
+    match response.system_error {
+        Some(error) => Err(anyhow!("provider {}: {}", provider, error)),
+        None => Ok(response),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn get_typed_values_test() -> Result<()> {
+        let response: ProviderResponse = serde_json::from_str(
+            r#"{"items": [
+                {"key": "prod", "value": {"team": "web"}},
+                {"key": "gone", "error": "namespace not found"}
+            ]}"#,
+        )?;
+        let labels: Option<BTreeMap<String, String>> = response.get("prod")?;
+        assert_eq!(labels.unwrap()["team"], "web");
+        assert!(response.get::<BTreeMap<String, String>>("gone").is_err());
+        assert_eq!(response.get::<Value>("missing")?, None);
+        assert!(
+            response.get::<Vec<String>>("prod").is_err(),
+            "A value of another type must be an error"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lookup_outside_of_webassembly_test() {
+        assert!(lookup("namespace-labels", &["prod"]).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Objects of the cluster other than the one under review, which Gatekeeper
+//! replicates as `data.inventory`, e.g. the Namespace of a pod.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::Request;
+
+/// Objects by `apiVersion`, then `kind`, then name.
+type Objects = BTreeMap<String, BTreeMap<String, BTreeMap<String, Value>>>;
+
+/// The inventory passed by the host, laid out like the one of Gatekeeper:
+/// cluster scoped objects under `cluster`, and namespaced ones under
+/// `namespace`, by namespace.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Inventory {
+    cluster: Objects,
+    namespace: BTreeMap<String, Objects>,
+}
+
+impl Inventory {
+    /// Parses the inventory: Gatekeeper's `data.inventory`, or the objects
+    /// themselves in any format [`Request::from_documents`] accepts, e.g. the
+    /// output of `kubectl get namespaces -o json`.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let requests =
+            Request::from_documents(raw).map_err(|e| anyhow!("invalid inventory: {}", e))?;
+        if let [request] = requests.as_slice() {
+            if request.kind().is_none() {
+                return Inventory::deserialize(request.object())
+                    .map_err(|e| anyhow!("invalid inventory: {}", e));
+            }
+        }
+        let mut inventory = Inventory::default();
+        for request in requests {
+            inventory.add(request.object().clone())?;
+        }
+        Ok(inventory)
+    }
+
+    /// Adds an object, under its namespace if it has one.
+    pub fn add(&mut self, object: Value) -> Result<()> {
+        let field = |pointer: &str| object.pointer(pointer).and_then(Value::as_str);
+        let (api_version, kind, name) = match (
+            field("/apiVersion"),
+            field("/kind"),
+            field("/metadata/name"),
+        ) {
+            (Some(api_version), Some(kind), Some(name)) => {
+                (api_version.to_string(), kind.to_string(), name.to_string())
+            }
+            _ => {
+                return Err(anyhow!(
+                    "invalid inventory: expected an object with an apiVersion, a kind and a name"
+                ))
+            }
+        };
+        let objects = match field("/metadata/namespace") {
+            Some(namespace) => self.namespace.entry(namespace.to_string()).or_default(),
+            None => &mut self.cluster,
+        };
+        objects
+            .entry(api_version)
+            .or_default()
+            .entry(kind)
+            .or_default()
+            .insert(name, object);
+        Ok(())
+    }
+
+    /// Returns an object, namespaced or, without a namespace, cluster scoped.
+    pub fn get(
+        &self,
+        namespace: Option<&str>,
+        api_version: &str,
+        kind: &str,
+        name: &str,
+    ) -> Option<&Value> {
+        let objects = match namespace {
+            Some(namespace) => self.namespace.get(namespace)?,
+            None => &self.cluster,
+        };
+        objects.get(api_version)?.get(kind)?.get(name)
+    }
+
+    /// Returns the objects of a kind in a namespace.
+    pub fn list(
+        &self,
+        namespace: &str,
+        api_version: &str,
+        kind: &str,
+    ) -> impl Iterator<Item = &Value> {
+        self.namespace
+            .get(namespace)
+            .and_then(|objects| objects.get(api_version))
+            .and_then(|kinds| kinds.get(kind))
+            .into_iter()
+            .flat_map(|names| names.values())
+    }
+
+    /// Deserializes the Namespace of the given name, e.g. into the type of
+    /// the Kubernetes API.
+    pub fn namespace<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>> {
+        self.get(None, "v1", "Namespace", name)
+            .map(deserialize)
+            .transpose()
+    }
+
+    /// Deserializes the Pods of a namespace.
+    pub fn pods<T: DeserializeOwned>(&self, namespace: &str) -> Result<Vec<T>> {
+        self.list(namespace, "v1", "Pod").map(deserialize).collect()
+    }
+
+    /// Deserializes the Services of a namespace.
+    pub fn services<T: DeserializeOwned>(&self, namespace: &str) -> Result<Vec<T>> {
+        self.list(namespace, "v1", "Service")
+            .map(deserialize)
+            .collect()
+    }
+}
+
+fn deserialize<T: DeserializeOwned>(object: &Value) -> Result<T> {
+    T::deserialize(object).map_err(|e| anyhow!("invalid inventory object: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Metadata {
+        name: String,
+        #[serde(default)]
+        labels: BTreeMap<String, String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Object {
+        metadata: Metadata,
+    }
+
+    const OBJECTS: &str = r#"
+apiVersion: v1
+kind: List
+items:
+  - apiVersion: v1
+    kind: Namespace
+    metadata:
+      name: prod
+      labels: {privileged-allowed: "true"}
+  - apiVersion: v1
+    kind: Pod
+    metadata: {name: web, namespace: prod}
+  - apiVersion: v1
+    kind: Pod
+    metadata: {name: api, namespace: prod}
+  - apiVersion: v1
+    kind: Service
+    metadata: {name: web, namespace: prod}
+"#;
+
+    #[test]
+    fn lookup_objects_test() -> Result<()> {
+        let inventory = Inventory::parse(OBJECTS)?;
+        let prod: Object = inventory.namespace("prod")?.unwrap();
+        assert_eq!(prod.metadata.labels["privileged-allowed"], "true");
+        assert!(inventory.namespace::<Object>("dev")?.is_none());
+
+        let pods: Vec<Object> = inventory.pods("prod")?;
+        let names: Vec<_> = pods.iter().map(|pod| pod.metadata.name.as_str()).collect();
+        assert_eq!(names, vec!["api", "web"]);
+        assert_eq!(inventory.services::<Object>("prod")?.len(), 1);
+        assert!(inventory.pods::<Object>("dev")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_gatekeeper_inventory_test() -> Result<()> {
+        let inventory = Inventory::parse(
+            r#"{
+  "cluster": {"v1": {"Namespace": {"prod": {"metadata": {"name": "prod"}}}}},
+  "namespace": {"prod": {"v1": {"Pod": {"web": {"metadata": {"name": "web"}}}}}}
+}"#,
+        )?;
+        assert!(inventory.namespace::<Object>("prod")?.is_some());
+        assert_eq!(
+            inventory.get(Some("prod"), "v1", "Pod", "web"),
+            Some(&serde_json::json!({"metadata": {"name": "web"}}))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_inventory_test() {
+        assert!(Inventory::parse(r#"{"kind": "Namespace"}"#).is_err());
+        assert!(Inventory::parse(r#"{"clusters": {}}"#).is_err());
+    }
+}
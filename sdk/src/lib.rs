@@ -0,0 +1,69 @@
+//! Runtime shared by the Rust policies compiled to WebAssembly.
+//!
+//! A policy implements [`Policy`] and marks the implementation with
+//! [`policy`], which generates the exports the host calls:
+//!
+//! ```ignore
+//! use policy_sdk::{policy, Decision, Metadata, Policy, Request};
+//!
+//! pub struct MyPolicy;
+//!
+//! #[policy]
+//! impl Policy for MyPolicy {
+//!     type Params = MyParameters;
+//!
+//!     fn metadata() -> Metadata { ... }
+//!
+//!     fn validate(request: &Request, params: &MyParameters) -> Decision { ... }
+//! }
+//! ```
+//!
+//! Besides WASI, the module imports `env.log(ptr, len)`, see [`logging`], and
+//! `env.now() -> i64`, see [`now`], from the host, along with
+//! `env.external_data(ptr, len) -> u64` when the policy uses
+//! [`external_data`].
+
+mod decision;
+pub mod external_data;
+mod inventory;
+pub mod logging;
+pub mod metadata;
+mod params;
+mod patch;
+mod request;
+pub mod runtime;
+
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+
+pub use decision::{Decision, EnforcementAction, Exemption, Severity, Violation};
+pub use inventory::Inventory;
+pub use metadata::Metadata;
+pub use params::{parse_parameters, schema_for};
+pub use patch::{Patch, PatchOperation};
+pub use policy_sdk_macros::policy;
+pub use request::{Operation, Request, UserInfo};
+pub use runtime::{
+    evaluate, log, mutation, now, review, review_with_inventory, Mutation, ObjectResult, Outcome,
+    Response,
+};
+
+/// A Kubernetes admission policy.
+pub trait Policy {
+    /// Parameters of the policy, deserialized from the `parameters` of the
+    /// constraint. [`Default`] provides the values used when the constraint
+    /// has none.
+    type Params: DeserializeOwned + JsonSchema + Default;
+
+    /// Describes the policy, see [`Metadata`].
+    fn metadata() -> Metadata;
+
+    /// Evaluates the request against the policy.
+    fn validate(request: &Request, params: &Self::Params) -> Decision;
+
+    /// Returns the changes bringing the object in line with the policy,
+    /// for hosts calling the `mutate` export. Nothing is changed by default.
+    fn mutate(_request: &Request, _params: &Self::Params) -> Vec<PatchOperation> {
+        Vec::new()
+    }
+}
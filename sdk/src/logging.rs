@@ -0,0 +1,165 @@
+//! Structured logs, written to the host through the `env.log` import as one
+//! JSON object per record, e.g.
+//! `{"level":"debug","msg":"rule matched","container":"nginx","rule":"privileged"}`.
+//!
+//! The host sets the most verbose level it wants through the
+//! `set_log_level` export, `info` unless it does. Records above that level
+//! are dropped before their fields are serialized, so debug logs cost next
+//! to nothing when disabled:
+//!
+//! ```ignore
+//! logging::debug("rule matched")
+//!     .field("rule", rule)
+//!     .field("container", &container.name)
+//!     .emit();
+//! ```
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::runtime;
+
+/// Severity of a record, from the most to the least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    /// The level passed by the host: 0 for `error`, 1 for `warn`, 2 for
+    /// `info` and 3, or more, for `debug`.
+    pub fn from_u32(level: u32) -> Level {
+        match level {
+            0 => Level::Error,
+            1 => Level::Warn,
+            2 => Level::Info,
+            _ => Level::Debug,
+        }
+    }
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// Sets the most verbose level written, see [`runtime::set_log_level`].
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// The most verbose level written.
+pub fn max_level() -> Level {
+    Level::from_u32(MAX_LEVEL.load(Ordering::Relaxed).into())
+}
+
+/// Whether records of the level are written.
+pub fn enabled(level: Level) -> bool {
+    level <= max_level()
+}
+
+/// A log record, written by [`Record::emit`].
+#[derive(Debug)]
+#[must_use = "records are only written by `emit`"]
+pub struct Record<'a> {
+    level: Level,
+    msg: &'a str,
+    /// The fields of the record, `None` when its level is disabled.
+    fields: Option<Map<String, Value>>,
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    level: Level,
+    msg: &'a str,
+    #[serde(flatten)]
+    fields: &'a Map<String, Value>,
+}
+
+impl<'a> Record<'a> {
+    pub fn new(level: Level, msg: &'a str) -> Self {
+        Record {
+            level,
+            msg,
+            fields: enabled(level).then(Map::new),
+        }
+    }
+
+    /// Adds a field to the record. The value is only serialized when the
+    /// level of the record is enabled.
+    pub fn field(mut self, key: &str, value: impl Serialize) -> Self {
+        if let Some(fields) = &mut self.fields {
+            let value = serde_json::to_value(value).unwrap_or(Value::Null);
+            fields.insert(key.to_string(), value);
+        }
+        self
+    }
+
+    /// The record as written to the host, `None` when its level is disabled.
+    pub fn to_json(&self) -> Option<String> {
+        let fields = self.fields.as_ref()?;
+        let line = Line {
+            level: self.level,
+            msg: self.msg,
+            fields,
+        };
+        Some(serde_json::to_string(&line).expect("serializing a log record"))
+    }
+
+    /// Writes the record, unless its level is disabled.
+    pub fn emit(self) {
+        if let Some(line) = self.to_json() {
+            runtime::write_log(&line);
+        }
+    }
+}
+
+pub fn error(msg: &str) -> Record<'_> {
+    Record::new(Level::Error, msg)
+}
+
+pub fn warn(msg: &str) -> Record<'_> {
+    Record::new(Level::Warn, msg)
+}
+
+pub fn info(msg: &str) -> Record<'_> {
+    Record::new(Level::Info, msg)
+}
+
+pub fn debug(msg: &str) -> Record<'_> {
+    Record::new(Level::Debug, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_to_json_test() {
+        let record = warn("provider failed")
+            .field("provider", "namespace-labels")
+            .field("keys", ["prod"]);
+        assert_eq!(
+            record.to_json().unwrap(),
+            r#"{"level":"warn","msg":"provider failed","keys":["prod"],"provider":"namespace-labels"}"#
+        );
+    }
+
+    #[test]
+    fn level_filter_test() {
+        assert_eq!(max_level(), Level::Info, "info must be the default");
+        assert!(info("written").to_json().is_some());
+        assert!(
+            debug("dropped")
+                .field("rule", "privileged")
+                .to_json()
+                .is_none(),
+            "Debug records must be dropped by default"
+        );
+        assert_eq!(Level::from_u32(1), Level::Warn);
+        assert_eq!(Level::from_u32(7), Level::Debug);
+    }
+}
@@ -0,0 +1,163 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// Version of the contract between the host and the module: the exports,
+/// the imports and the way strings cross the boundary. Bump it whenever one
+/// of them changes in a way existing hosts cannot cope with.
+///
+/// - 2: the module imports `env.now`, besides `env.log`.
+/// - 3: the module imports `env.external_data`.
+pub const ABI_VERSION: u32 = 3;
+
+/// Name of the custom section holding the same document as the `metadata`
+/// export, for tools inspecting `policy.wasm` without instantiating it.
+pub const CUSTOM_SECTION: &str = "policy-metadata";
+
+/// Description of a policy, returned by the `metadata` export.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+    pub name: &'static str,
+    pub title: &'static str,
+    pub version: &'static str,
+    /// Constraint kind created from the ConstraintTemplate.
+    pub constraint_kind: &'static str,
+    /// Kinds the policy knows how to evaluate, in the format of a
+    /// constraint's `match.kinds`.
+    pub kinds: Vec<MatchKinds>,
+    /// OpenAPI v3 schema of the constraint parameters, see
+    /// [`crate::schema_for`].
+    pub parameters: Value,
+    pub mutating: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchKinds {
+    pub api_groups: Vec<&'static str>,
+    pub kinds: Vec<&'static str>,
+}
+
+/// The metadata document along with the ABI version of the SDK it was built
+/// with.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Document<'a> {
+    #[serde(flatten)]
+    metadata: &'a Metadata,
+    abi_version: u32,
+}
+
+impl Metadata {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&Document {
+            metadata: self,
+            abi_version: ABI_VERSION,
+        })
+        .unwrap_or_default()
+    }
+}
+
+/// Appends a custom section to a WebAssembly binary.
+pub fn append_custom_section(wasm: &mut Vec<u8>, name: &str, payload: &[u8]) {
+    let mut content = Vec::new();
+    write_leb128(&mut content, name.len() as u32);
+    content.extend_from_slice(name.as_bytes());
+    content.extend_from_slice(payload);
+
+    // Custom sections have the id 0.
+    wasm.push(0);
+    write_leb128(wasm, content.len() as u32);
+    wasm.extend_from_slice(&content);
+}
+
+/// Returns the payload of the first custom section with the given name, or
+/// None if there is none or the binary is malformed.
+pub fn custom_section<'a>(wasm: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    // Skip the magic number and the version.
+    let mut rest = wasm.get(8..)?;
+    while !rest.is_empty() {
+        let id = rest[0];
+        let (size, read) = read_leb128(&rest[1..])?;
+        let section = rest.get(1 + read..1 + read + size as usize)?;
+        rest = &rest[1 + read + size as usize..];
+        if id != 0 {
+            continue;
+        }
+        let (name_len, read) = read_leb128(section)?;
+        let section_name = section.get(read..read + name_len as usize)?;
+        if section_name == name.as_bytes() {
+            return section.get(read + name_len as usize..);
+        }
+    }
+    None
+}
+
+fn write_leb128(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_leb128(bytes: &[u8]) -> Option<(u32, usize)> {
+    let mut value = 0u32;
+    for (i, byte) in bytes.iter().take(5).enumerate() {
+        value |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    /// The smallest valid module: the magic number and the version.
+    const EMPTY_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+    #[test]
+    fn metadata_document_test() -> Result<()> {
+        let metadata = Metadata {
+            name: "example",
+            title: "Example",
+            version: "0.1.0",
+            constraint_kind: "Example",
+            kinds: vec![MatchKinds {
+                api_groups: vec![""],
+                kinds: vec!["Pod"],
+            }],
+            parameters: Value::Null,
+            mutating: false,
+        };
+        let document: Value = serde_json::from_str(&metadata.to_json())?;
+        assert_eq!(document["name"], "example");
+        assert_eq!(document["abiVersion"], 3);
+        assert_eq!(document["constraintKind"], "Example");
+        assert_eq!(document["kinds"][0]["apiGroups"][0], "");
+        assert_eq!(document["mutating"], false);
+        Ok(())
+    }
+
+    #[test]
+    fn custom_section_roundtrip_test() {
+        let mut wasm = EMPTY_MODULE.to_vec();
+        append_custom_section(&mut wasm, "other", b"ignored");
+        let payload = "x".repeat(300);
+        append_custom_section(&mut wasm, CUSTOM_SECTION, payload.as_bytes());
+        assert_eq!(
+            custom_section(&wasm, CUSTOM_SECTION),
+            Some(payload.as_bytes())
+        );
+        assert_eq!(custom_section(&wasm, "missing"), None);
+        assert_eq!(custom_section(EMPTY_MODULE, CUSTOM_SECTION), None);
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Result};
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Parses the raw `parameters` argument handed over by the host. An empty
+/// argument or `null` means the constraint has no parameters, in which case
+/// the defaults apply.
+///
+/// Parameter types should use `#[serde(deny_unknown_fields)]`, so that a
+/// typo in a constraint surfaces as an error instead of silently falling
+/// back to the defaults.
+pub fn parse_parameters<P: DeserializeOwned + Default>(raw: &str) -> Result<P> {
+    if raw.trim().is_empty() {
+        return Ok(P::default());
+    }
+    serde_json::from_str::<Option<P>>(raw)
+        .map(Option::unwrap_or_default)
+        .map_err(|e| anyhow!("invalid parameters: {}", e))
+}
+
+/// Returns the OpenAPI v3 schema of the parameters, in the structural form
+/// Kubernetes expects in a CRD validation block. The doc comments of the
+/// fields become the descriptions.
+pub fn schema_for<P: JsonSchema>() -> Value {
+    let mut settings = SchemaSettings::openapi3();
+    settings.inline_subschemas = true;
+    let schema = settings.into_generator().into_root_schema_for::<P>();
+    let mut schema = serde_json::to_value(schema.schema).unwrap_or_default();
+    make_structural(&mut schema);
+    schema
+}
+
+/// Drops the JSON Schema keywords which are not allowed in a structural
+/// schema: the document title and `additionalProperties: false`, which
+/// Kubernetes refuses next to `properties`.
+fn make_structural(schema: &mut Value) {
+    if let Value::Object(map) = schema {
+        if matches!(map.get("title"), Some(Value::String(_))) {
+            map.remove("title");
+        }
+        if map.get("additionalProperties") == Some(&Value::Bool(false)) {
+            map.remove("additionalProperties");
+        }
+        for value in map.values_mut() {
+            make_structural(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EnforcementAction;
+    use serde::Deserialize;
+
+    #[derive(Debug, Default, PartialEq, Deserialize, JsonSchema)]
+    #[serde(rename_all = "camelCase", deny_unknown_fields, default)]
+    struct Params {
+        /// Names to look for.
+        names: Vec<String>,
+        nested: Nested,
+        action: EnforcementAction,
+    }
+
+    #[derive(Debug, Default, PartialEq, Deserialize, JsonSchema)]
+    #[serde(deny_unknown_fields, default)]
+    struct Nested {
+        title: Option<String>,
+    }
+
+    #[test]
+    fn empty_parameters_use_defaults_test() -> Result<()> {
+        assert_eq!(parse_parameters::<Params>("")?, Params::default());
+        assert_eq!(parse_parameters::<Params>("null")?, Params::default());
+        assert_eq!(parse_parameters::<Params>("{}")?, Params::default());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unknown_parameter_test() {
+        let result = parse_parameters::<Params>(r#"{"name":["nginx"]}"#);
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .starts_with("invalid parameters: unknown field `name`"));
+    }
+
+    #[test]
+    fn schema_is_structural_test() {
+        let schema = schema_for::<Params>();
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["properties"]["names"]["type"], "array");
+        assert_eq!(
+            schema["properties"]["names"]["description"],
+            "Names to look for."
+        );
+        assert_eq!(schema["properties"]["nested"]["type"], "object");
+        assert!(schema["properties"]["nested"]["properties"]["title"].is_object());
+        assert!(schema.get("additionalProperties").is_none());
+        assert!(schema["properties"]["nested"]
+            .get("additionalProperties")
+            .is_none());
+        assert!(schema.get("title").is_none());
+        assert_eq!(
+            schema["properties"]["action"]["enum"],
+            serde_json::json!(["deny", "warn", "dryrun"])
+        );
+        assert!(schema["properties"]["action"].get("oneOf").is_none());
+    }
+}
@@ -0,0 +1,243 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// A JSONPatch operation, see RFC 6902. Policies only set values, so only
+/// `add` and `replace` are produced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Replace { path: String, value: Value },
+}
+
+/// Builds a JSONPatch for an object. The changes are applied to a copy of the
+/// object as they are made, so every operation is valid after the previous
+/// ones, e.g. a `securityContext` added by one is reused by the next.
+#[derive(Debug, Clone)]
+pub struct Patch {
+    object: Value,
+    operations: Vec<PatchOperation>,
+}
+
+impl Patch {
+    pub fn new(object: Value) -> Self {
+        Patch {
+            object,
+            operations: Vec::new(),
+        }
+    }
+
+    /// The object with the changes made so far.
+    pub fn object(&self) -> &Value {
+        &self.object
+    }
+
+    pub fn operations(&self) -> &[PatchOperation] {
+        &self.operations
+    }
+
+    pub fn into_operations(self) -> Vec<PatchOperation> {
+        self.operations
+    }
+
+    /// Sets the value at the given JSON pointer. Missing parents are added
+    /// along with the value, and nothing is done when the value is already
+    /// set. Values are only added to arrays at their end, with the index `-`
+    /// or the length of the array, and missing arrays are added for the
+    /// indexes `-` and `0`; the value is skipped for other indexes, as it
+    /// could not be added where asked.
+    pub fn set(&mut self, pointer: &str, value: Value) {
+        let segments: Vec<String> = pointer
+            .split('/')
+            .skip(1)
+            .map(|s| s.replace("~1", "/").replace("~0", "~"))
+            .collect();
+
+        // Find the deepest existing parent of the value.
+        let mut current = &self.object;
+        let mut depth = 0;
+        while let Some(child) = segments.get(depth).and_then(|s| child(current, s)) {
+            current = child;
+            depth += 1;
+        }
+
+        if depth == segments.len() {
+            if *current != value {
+                *self.object.pointer_mut(pointer).expect("existing value") = value.clone();
+                self.operations.push(PatchOperation::Replace {
+                    path: pointer.to_string(),
+                    value,
+                });
+            }
+            return;
+        }
+
+        // Nest the value in the missing parents.
+        let value = match segments[depth + 1..]
+            .iter()
+            .rev()
+            .try_fold(value, |value, segment| nest(segment, value))
+        {
+            Some(value) => value,
+            None => return,
+        };
+        let path = pointer_of(&segments[..=depth]);
+        let parent = self
+            .object
+            .pointer_mut(&pointer_of(&segments[..depth]))
+            .expect("existing parent");
+        match parent {
+            Value::Object(map) => {
+                map.insert(segments[depth].clone(), value.clone());
+                self.operations.push(PatchOperation::Add { path, value });
+            }
+            Value::Array(items) => {
+                let index = &segments[depth];
+                if index != "-" && *index != items.len().to_string() {
+                    return;
+                }
+                items.push(value.clone());
+                self.operations.push(PatchOperation::Add { path, value });
+            }
+            // The parent is null or a scalar: replace it.
+            other => {
+                let value = match nest(&segments[depth], value) {
+                    Some(value) => value,
+                    None => return,
+                };
+                *other = value.clone();
+                self.operations.push(PatchOperation::Replace {
+                    path: pointer_of(&segments[..depth]),
+                    value,
+                });
+            }
+        }
+    }
+}
+
+fn child<'v>(value: &'v Value, segment: &str) -> Option<&'v Value> {
+    match value {
+        Value::Object(map) => map.get(segment),
+        Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+        _ => None,
+    }
+}
+
+/// Returns the value in a new parent under the given segment: an array for
+/// the indexes `-` and `0`, an object for other keys. Returns `None` for
+/// other indexes, past the end of the new array.
+fn nest(segment: &str, value: Value) -> Option<Value> {
+    match segment {
+        "-" | "0" => Some(Value::Array(vec![value])),
+        _ if !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()) => None,
+        _ => Some(Value::Object(Map::from_iter([(
+            segment.to_string(),
+            value,
+        )]))),
+    }
+}
+
+fn pointer_of(segments: &[String]) -> String {
+    segments
+        .iter()
+        .map(|s| format!("/{}", s.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn set_adds_missing_parents_once_test() {
+        let mut patch = Patch::new(json!({"spec": {"containers": [{"name": "app"}]}}));
+        patch.set(
+            "/spec/containers/0/securityContext/privileged",
+            json!(false),
+        );
+        patch.set(
+            "/spec/containers/0/securityContext/allowPrivilegeEscalation",
+            json!(false),
+        );
+        assert_eq!(
+            serde_json::to_value(patch.operations()).unwrap(),
+            json!([
+                {
+                    "op": "add",
+                    "path": "/spec/containers/0/securityContext",
+                    "value": {"privileged": false},
+                },
+                {
+                    "op": "add",
+                    "path": "/spec/containers/0/securityContext/allowPrivilegeEscalation",
+                    "value": false,
+                },
+            ])
+        );
+        assert_eq!(
+            patch.object()["spec"]["containers"][0]["securityContext"],
+            json!({"privileged": false, "allowPrivilegeEscalation": false})
+        );
+    }
+
+    #[test]
+    fn set_replaces_existing_values_test() {
+        let mut patch = Patch::new(json!({"spec": {"hostPID": true, "hostIPC": false}}));
+        patch.set("/spec/hostPID", json!(false));
+        patch.set("/spec/hostIPC", json!(false));
+        assert_eq!(
+            patch.into_operations(),
+            vec![PatchOperation::Replace {
+                path: "/spec/hostPID".to_string(),
+                value: json!(false),
+            }],
+            "Values already set must not be patched"
+        );
+    }
+
+    #[test]
+    fn set_only_appends_to_arrays_test() {
+        let object = json!({"spec": {"containers": [{"name": "app"}]}});
+        let mut patch = Patch::new(object.clone());
+        patch.set("/spec/containers/3/name", json!("sidecar"));
+        patch.set("/spec/initContainers/2/name", json!("init"));
+        assert!(
+            patch.operations().is_empty(),
+            "A value out of the range of an array must not be added"
+        );
+        assert_eq!(patch.object(), &object);
+
+        patch.set("/spec/containers/1", json!({"name": "sidecar"}));
+        patch.set("/spec/containers/-", json!({"name": "debug"}));
+        patch.set("/spec/initContainers/0/name", json!("init"));
+        assert_eq!(
+            serde_json::to_value(patch.operations()).unwrap(),
+            json!([
+                {"op": "add", "path": "/spec/containers/1", "value": {"name": "sidecar"}},
+                {"op": "add", "path": "/spec/containers/-", "value": {"name": "debug"}},
+                {"op": "add", "path": "/spec/initContainers", "value": [{"name": "init"}]},
+            ])
+        );
+        assert_eq!(
+            patch.object()["spec"],
+            json!({
+                "containers": [{"name": "app"}, {"name": "sidecar"}, {"name": "debug"}],
+                "initContainers": [{"name": "init"}],
+            })
+        );
+    }
+
+    #[test]
+    fn set_replaces_null_parents_test() {
+        let mut patch = Patch::new(json!({"securityContext": null}));
+        patch.set("/securityContext/privileged", json!(false));
+        assert_eq!(
+            patch.into_operations(),
+            vec![PatchOperation::Replace {
+                path: "/securityContext".to_string(),
+                value: json!({"privileged": false}),
+            }]
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::Inventory;
+
+/// The object under review, as handed over by the host. When the host hands
+/// over an `AdmissionReview`, the request also carries the previous version
+/// of the object, the operation and who is asking. The host may also pass
+/// the other objects of the cluster, see [`Inventory`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    object: Value,
+    old_object: Option<Value>,
+    operation: Option<Operation>,
+    subresource: Option<String>,
+    namespace: Option<String>,
+    user_info: Option<UserInfo>,
+    inventory: Option<Arc<Inventory>>,
+}
+
+/// The operation of an admission request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Operation {
+    Create,
+    Update,
+    Delete,
+    Connect,
+}
+
+/// The user making an admission request.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct UserInfo {
+    pub username: String,
+    pub groups: Vec<String>,
+}
+
+/// The fields of the `request` of an `AdmissionReview` used by the policies.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AdmissionRequest {
+    #[serde(default)]
+    object: Value,
+    old_object: Option<Value>,
+    operation: Option<Operation>,
+    sub_resource: Option<String>,
+    namespace: Option<String>,
+    user_info: Option<UserInfo>,
+}
+
+impl Request {
+    pub fn new(object: Value) -> Self {
+        Request {
+            object,
+            old_object: None,
+            operation: None,
+            subresource: None,
+            namespace: None,
+            user_info: None,
+            inventory: None,
+        }
+    }
+
+    /// Takes the request out of an `AdmissionReview`.
+    pub fn from_admission_review(review: &Value) -> Result<Self> {
+        let request = review
+            .get("request")
+            .ok_or_else(|| anyhow!("invalid admission review: no request"))?;
+        let request = AdmissionRequest::deserialize(request)
+            .map_err(|e| anyhow!("invalid admission review: {}", e))?;
+        Ok(Request {
+            object: request.object,
+            old_object: request.old_object,
+            operation: request.operation,
+            subresource: request.sub_resource,
+            namespace: request.namespace,
+            user_info: request.user_info,
+            inventory: None,
+        })
+    }
+
+    /// Parses the JSON document passed by the host.
+    pub fn from_json(raw: &str) -> Result<Self> {
+        serde_json::from_str(raw)
+            .map(Request::new)
+            .map_err(|e| anyhow!("invalid object: {}", e))
+    }
+
+    /// Parses the objects passed by the host: a JSON document, or a YAML
+    /// stream of `---` separated documents. `List`s are replaced by their
+    /// items, `AdmissionReview`s by their request, and empty documents are
+    /// skipped.
+    pub fn from_documents(raw: &str) -> Result<Vec<Self>> {
+        let documents = match serde_json::from_str(raw) {
+            Ok(document) => vec![document],
+            Err(_) => serde_yaml::Deserializer::from_str(raw)
+                .map(Value::deserialize)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| anyhow!("invalid object: {}", e))?,
+        };
+        let mut requests = Vec::new();
+        for document in documents {
+            flatten_lists(document, &mut requests)?;
+        }
+        if requests.is_empty() {
+            return Err(anyhow!("invalid object: no object to review"));
+        }
+        Ok(requests)
+    }
+
+    /// The object, null when it is deleted.
+    pub fn object(&self) -> &Value {
+        &self.object
+    }
+
+    /// The object before an UPDATE or a DELETE.
+    pub fn old_object(&self) -> Option<&Value> {
+        self.old_object.as_ref()
+    }
+
+    pub fn operation(&self) -> Option<Operation> {
+        self.operation
+    }
+
+    /// The subresource the request is about, e.g. `ephemeralcontainers` for
+    /// the ephemeral containers added by `kubectl debug`.
+    pub fn subresource(&self) -> Option<&str> {
+        self.subresource.as_deref()
+    }
+
+    /// The namespace of the object: the one of the admission request, set
+    /// even when the object leaves it to the request, or else the one in the
+    /// metadata of the object.
+    pub fn namespace(&self) -> Option<&str> {
+        let object = match (&self.object, &self.old_object) {
+            (Value::Null, Some(old_object)) => old_object,
+            (object, _) => object,
+        };
+        self.namespace
+            .as_deref()
+            .or_else(|| object["metadata"]["namespace"].as_str())
+    }
+
+    pub fn user_info(&self) -> Option<&UserInfo> {
+        self.user_info.as_ref()
+    }
+
+    /// The inventory passed by the host along with the object, if any.
+    pub fn inventory(&self) -> Option<&Inventory> {
+        self.inventory.as_deref()
+    }
+
+    /// Returns the request with the given inventory, shared by the objects
+    /// reviewed together.
+    pub fn with_inventory(self, inventory: Arc<Inventory>) -> Self {
+        Request {
+            inventory: Some(inventory),
+            ..self
+        }
+    }
+
+    /// Returns the `kind` of the object, if any.
+    pub fn kind(&self) -> Option<&str> {
+        self.object.get("kind").and_then(Value::as_str)
+    }
+
+    /// Identifies the object in results: `kind/namespace/name`, or
+    /// `kind/name` for cluster scoped objects. Deleted objects are
+    /// identified by their old version.
+    pub fn key(&self) -> String {
+        let object = match (&self.object, &self.old_object) {
+            (Value::Null, Some(old_object)) => old_object,
+            (object, _) => object,
+        };
+        let kind = object["kind"].as_str().unwrap_or_default();
+        let metadata = &object["metadata"];
+        let name = metadata["name"].as_str().unwrap_or_default();
+        match metadata["namespace"].as_str() {
+            Some(namespace) => format!("{}/{}/{}", kind, namespace, name),
+            None => format!("{}/{}", kind, name),
+        }
+    }
+
+    /// Deserializes the object into one of the Kubernetes API types.
+    pub fn object_as<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(T::deserialize(&self.object)?)
+    }
+}
+
+fn flatten_lists(document: Value, requests: &mut Vec<Request>) -> Result<()> {
+    let kind = document["kind"].as_str().unwrap_or_default();
+    let is_list = kind.ends_with("List");
+    match document {
+        Value::Null => {}
+        review @ Value::Object(_) if kind == "AdmissionReview" => {
+            requests.push(Request::from_admission_review(&review)?)
+        }
+        Value::Object(mut list) if is_list && list.get("items").is_some_and(Value::is_array) => {
+            if let Some(Value::Array(items)) = list.remove("items") {
+                for item in items {
+                    flatten_lists(item, requests)?;
+                }
+            }
+        }
+        object @ Value::Object(_) => requests.push(Request::new(object)),
+        other => return Err(anyhow!("invalid object: expected an object, got {}", other)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_from_json_test() -> Result<()> {
+        let request = Request::from_json(r#"{"kind":"Pod","metadata":{"name":"nginx"}}"#)?;
+        assert_eq!(request.kind(), Some("Pod"));
+        assert_eq!(request.object()["metadata"]["name"], "nginx");
+        Ok(())
+    }
+
+    #[test]
+    fn request_from_documents_test() -> Result<()> {
+        let requests = Request::from_documents(
+            r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: nginx
+  namespace: default
+---
+---
+apiVersion: v1
+kind: List
+items:
+  - kind: Namespace
+    metadata:
+      name: prod
+  - kind: Pod
+    metadata:
+      name: web
+      namespace: prod
+"#,
+        )?;
+        let keys: Vec<_> = requests.iter().map(Request::key).collect();
+        assert_eq!(
+            keys,
+            vec!["Pod/default/nginx", "Namespace/prod", "Pod/prod/web"]
+        );
+
+        let requests = Request::from_documents(r#"{"kind":"Pod","metadata":{"name":"nginx"}}"#)?;
+        assert_eq!(requests.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn request_from_admission_review_test() -> Result<()> {
+        let requests = Request::from_documents(
+            r#"
+apiVersion: admission.k8s.io/v1
+kind: AdmissionReview
+request:
+  operation: UPDATE
+  subResource: ephemeralcontainers
+  namespace: prod
+  userInfo:
+    username: alice
+    groups: [system:authenticated]
+  object:
+    kind: Pod
+    metadata: {name: nginx, namespace: default, labels: {app: web}}
+  oldObject:
+    kind: Pod
+    metadata: {name: nginx, namespace: default}
+---
+apiVersion: admission.k8s.io/v1
+kind: AdmissionReview
+request:
+  operation: DELETE
+  object: null
+  oldObject:
+    kind: Pod
+    metadata: {name: web, namespace: default}
+"#,
+        )?;
+        assert_eq!(requests.len(), 2);
+
+        let update = &requests[0];
+        assert_eq!(update.operation(), Some(Operation::Update));
+        assert_eq!(update.subresource(), Some("ephemeralcontainers"));
+        assert_eq!(update.namespace(), Some("prod"));
+        assert_eq!(
+            update.user_info(),
+            Some(&UserInfo {
+                username: "alice".to_string(),
+                groups: vec!["system:authenticated".to_string()],
+            })
+        );
+        assert_eq!(update.kind(), Some("Pod"));
+        assert_eq!(update.object()["metadata"]["labels"]["app"], "web");
+        assert_eq!(
+            update.old_object().map(|o| &o["metadata"]["name"]),
+            Some(&Value::from("nginx"))
+        );
+
+        let delete = &requests[1];
+        assert_eq!(delete.operation(), Some(Operation::Delete));
+        assert_eq!(delete.subresource(), None);
+        assert_eq!(delete.namespace(), Some("default"));
+        assert_eq!(delete.object(), &Value::Null);
+        assert_eq!(delete.key(), "Pod/default/web");
+
+        assert!(
+            Request::from_documents(r#"{"kind":"AdmissionReview"}"#).is_err(),
+            "A review without a request must be an error"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_documents_test() {
+        assert!(
+            Request::from_documents("").is_err(),
+            "No object must be an error"
+        );
+        assert!(Request::from_documents("kind: [Pod").is_err());
+        assert!(Request::from_documents("not an object").is_err());
+    }
+
+    #[test]
+    fn reject_invalid_json_test() {
+        assert!(Request::from_json("{").is_err());
+    }
+}
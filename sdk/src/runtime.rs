@@ -0,0 +1,689 @@
+//! The host interface: imports, memory management and the entrypoints the
+//! [`crate::policy`] macro exports.
+
+use std::env;
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::logging::{self, Level};
+use crate::{
+    parse_parameters, Decision, EnforcementAction, Exemption, Inventory, PatchOperation, Policy,
+    Request, Violation,
+};
+
+/// Logs a message without fields at the `info` level, see [`crate::logging`]
+/// for structured logs.
+pub fn log(message: &str) {
+    logging::info(message).emit();
+}
+
+/// Writes a log line to the console using [`_log`]. Outside of WebAssembly,
+/// e.g. in tests and command-line tools, the line goes to stderr.
+pub(crate) fn write_log(line: &str) {
+    #[cfg(target_arch = "wasm32")]
+    unsafe {
+        let (ptr, len) = string_to_ptr(line);
+        _log(ptr, len);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    eprintln!("{}", line);
+}
+
+/// Backs the `set_log_level` export, which the host calls before the others
+/// to choose the most verbose level logged, see [`Level::from_u32`].
+pub fn set_log_level(level: u32) {
+    logging::set_max_level(Level::from_u32(level));
+}
+
+/// Returns the current time, in seconds since the Unix epoch, as given by
+/// the host through [`_now`]. Policies get the time from here rather than
+/// from the WASI clock, so that hosts can evaluate them at a fixed time.
+/// Outside of WebAssembly, the time comes from the system clock.
+pub fn now() -> i64 {
+    #[cfg(target_arch = "wasm32")]
+    unsafe {
+        _now()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Sends a request to the host's external data providers through
+/// [`_external_data`] and returns the response, see
+/// [`crate::external_data`].
+pub(crate) fn external_data(request: &str) -> anyhow::Result<String> {
+    #[cfg(target_arch = "wasm32")]
+    unsafe {
+        let (ptr, len) = string_to_ptr(request);
+        let packed = _external_data(ptr, len);
+        let (ptr, size) = ((packed >> 32) as u32, packed as u32);
+        Ok(string_from_host(ptr as *mut u8, size as usize))
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    Err(anyhow::anyhow!(
+        "no external data provider outside of WebAssembly, for {}",
+        request
+    ))
+}
+
+#[cfg(target_arch = "wasm32")]
+#[link(wasm_import_module = "env")]
+extern "C" {
+    /// WebAssembly import which prints a string (linear memory offset,
+    /// byteCount) to the console.
+    ///
+    /// Note: This is not an ownership transfer: Rust still owns the pointer
+    /// and ensures it isn't deallocated during this call.
+    #[link_name = "log"]
+    fn _log(ptr: u32, size: u32);
+
+    /// WebAssembly import which returns the current time, in seconds since
+    /// the Unix epoch.
+    #[link_name = "now"]
+    fn _now() -> i64;
+
+    /// WebAssembly import which sends a request (linear memory offset,
+    /// byteCount) to the external data providers of the host. The response
+    /// is written to a buffer from [`allocate`], whose offset and byteCount
+    /// are returned packed like in [`string_to_host`]. This is an ownership
+    /// transfer of the response, not of the request.
+    #[link_name = "external_data"]
+    fn _external_data(ptr: u32, size: u32) -> u64;
+}
+
+/// Returns a pointer and size pair for the given string in a way compatible
+/// with WebAssembly numeric types.
+///
+/// Note: This doesn't change the ownership of the String. To intentionally
+/// leak it, use [`std::mem::forget`] on the input after calling this.
+///
+/// # Safety
+///
+/// The returned pair is only valid while `s` is alive and unchanged.
+#[cfg(target_arch = "wasm32")]
+unsafe fn string_to_ptr(s: &str) -> (u32, u32) {
+    (s.as_ptr() as u32, s.len() as u32)
+}
+
+/// Leaks the given string to the host, returning its linear memory offset
+/// and byteCount packed into a single integer: the offset in the upper 32
+/// bits and the byteCount in the lower ones.
+///
+/// This is an ownership transfer, which means the caller must call
+/// [`deallocate`] with the unpacked pair when finished.
+pub fn string_to_host(s: String) -> u64 {
+    let bytes = s.into_bytes().into_boxed_slice();
+    let len = bytes.len() as u64;
+    let ptr = Box::into_raw(bytes) as *mut u8 as u32 as u64;
+    (ptr << 32) | len
+}
+
+/// Set the global allocator to the WebAssembly optimized one.
+#[cfg(target_arch = "wasm32")]
+#[global_allocator]
+static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
+
+/// Allocates size bytes and leaks the pointer where they start.
+///
+/// Backs the `allocate` export, which hands out a pointer (linear memory
+/// offset) that can be used for a string. This is an ownership transfer,
+/// which means the caller must call [`deallocate`] when finished.
+pub fn allocate(size: usize) -> *mut u8 {
+    // Allocate the amount of bytes needed. Not through a boxed slice, which
+    // would shrink the allocation to the length of the empty vector.
+    let mut vec: Vec<MaybeUninit<u8>> = Vec::with_capacity(size);
+    let ptr = vec.as_mut_ptr() as *mut u8;
+
+    // Leak the memory to the caller.
+    std::mem::forget(vec);
+    ptr
+}
+
+/// Takes back a string the host wrote to a buffer returned by [`allocate`],
+/// freeing the buffer.
+///
+/// # Safety
+///
+/// `ptr` must have been returned by [`allocate`] for `size` bytes, all of
+/// them written by the host, and must not be used afterwards.
+unsafe fn string_from_host(ptr: *mut u8, size: usize) -> String {
+    if size == 0 {
+        return String::new();
+    }
+    let bytes = Vec::from_raw_parts(ptr, size, size);
+    match String::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    }
+}
+
+/// Retakes the pointer which allows its memory to be freed.
+///
+/// # Safety
+///
+/// `ptr` and `size` must describe a buffer returned by [`allocate`] or
+/// [`string_to_host`] that has not been deallocated yet.
+pub unsafe fn deallocate(ptr: *mut u8, size: usize) {
+    let _ = Vec::from_raw_parts(ptr, 0, size);
+}
+
+/// Result of an evaluation, printed to stdout for the host to read.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Allowed,
+    Denied,
+    /// The constraint parameters, or the inventory, could not be understood.
+    /// This is reported on its own, so that a broken constraint never looks
+    /// like an allowed request.
+    InvalidParameters(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Allowed => write!(f, "true"),
+            Outcome::Denied => write!(f, "false"),
+            Outcome::InvalidParameters(reason) => write!(f, "error: {}", reason),
+        }
+    }
+}
+
+/// Result of an evaluation with the reasons behind it, returned as JSON by
+/// the `validate` export.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Response {
+    /// Whether all the objects are allowed.
+    pub allowed: bool,
+    /// The violations of all the objects.
+    pub violations: Vec<Violation>,
+    /// The exemptions applied to all the objects.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exemptions: Vec<Exemption>,
+    /// The messages of the violations in `warn` mode, for the host to return
+    /// as admission warnings.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// The result of each object, when the input could be parsed.
+    pub results: Vec<ObjectResult>,
+    /// Why the request could not be evaluated, see
+    /// [`Outcome::InvalidParameters`]. The request is never allowed then.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Result of one of the objects passed to the policy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ObjectResult {
+    /// Identifies the object, see [`Request::key`].
+    pub key: String,
+    pub allowed: bool,
+    pub violations: Vec<Violation>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exemptions: Vec<Exemption>,
+    /// How the decision on the object was reached, see [`Decision::trace`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace: Option<serde_json::Value>,
+}
+
+impl From<&Response> for Outcome {
+    fn from(response: &Response) -> Self {
+        match &response.error {
+            Some(reason) => Outcome::InvalidParameters(reason.clone()),
+            None if response.allowed => Outcome::Allowed,
+            None => Outcome::Denied,
+        }
+    }
+}
+
+/// Evaluates the objects to test against the policy, with the raw constraint
+/// parameters, and returns the violations found. See
+/// [`Request::from_documents`] for the accepted input.
+pub fn review<P: Policy>(objects_to_test: &str, parameters: &str) -> Response {
+    review_with_inventory::<P>(objects_to_test, parameters, "")
+}
+
+/// Same as [`review`], with the raw inventory, see [`Inventory::parse`]. An
+/// empty inventory leaves the requests without one.
+pub fn review_with_inventory<P: Policy>(
+    objects_to_test: &str,
+    parameters: &str,
+    inventory: &str,
+) -> Response {
+    let error = |e: anyhow::Error| Response {
+        error: Some(e.to_string()),
+        ..Response::default()
+    };
+    let params = match parse_parameters::<P::Params>(parameters) {
+        Ok(params) => params,
+        Err(e) => return error(e),
+    };
+    let inventory = match inventory.trim() {
+        "" => None,
+        inventory => match Inventory::parse(inventory) {
+            Ok(inventory) => Some(Arc::new(inventory)),
+            Err(e) => return error(e),
+        },
+    };
+    let requests = match Request::from_documents(objects_to_test) {
+        Ok(requests) => requests,
+        Err(e) => {
+            return Response {
+                violations: Decision::deny(e.to_string()).violations,
+                ..Response::default()
+            }
+        }
+    };
+
+    let mut response = Response {
+        allowed: true,
+        ..Response::default()
+    };
+    for mut request in requests {
+        if let Some(inventory) = &inventory {
+            request = request.with_inventory(Arc::clone(inventory));
+        }
+        let decision = P::validate(&request, &params);
+        response.allowed &= decision.is_allowed();
+        response.warnings.extend(
+            decision
+                .warnings()
+                .map(|violation| violation.message.clone()),
+        );
+        response
+            .violations
+            .extend(decision.violations.iter().cloned());
+        response
+            .exemptions
+            .extend(decision.exemptions.iter().cloned());
+        response.results.push(ObjectResult {
+            key: request.key(),
+            allowed: decision.is_allowed(),
+            violations: decision.violations,
+            exemptions: decision.exemptions,
+            trace: decision.trace,
+        });
+    }
+    response
+}
+
+/// Evaluates the object to test against the policy, with the raw constraint
+/// parameters.
+pub fn evaluate<P: Policy>(object_to_test: &str, parameters: &str) -> Outcome {
+    Outcome::from(&review::<P>(object_to_test, parameters))
+}
+
+/// Backs the `eval` export: reads the objects to test, the constraint
+/// parameters and the optional inventory from the program arguments, and
+/// prints the [`Outcome`]. When there are several objects, the violations
+/// denying each are logged, with its key. Warnings and exemptions are always
+/// logged, as the output has no room for them.
+pub fn eval<P: Policy>() {
+    let args: Vec<_> = env::args().collect();
+    let objects_to_test = args.get(1).map(String::as_str).unwrap_or_default();
+    let parameters = args.get(2).map(String::as_str).unwrap_or_default();
+    let inventory = args.get(3).map(String::as_str).unwrap_or_default();
+
+    let response = review_with_inventory::<P>(objects_to_test, parameters, inventory);
+    if let Some(reason) = &response.error {
+        logging::error(reason).emit();
+    }
+    if response.results.len() > 1 {
+        for result in &response.results {
+            let denying = result
+                .violations
+                .iter()
+                .filter(|violation| violation.enforcement_action == EnforcementAction::Deny);
+            for violation in denying {
+                logging::info("violation")
+                    .field("object", &result.key)
+                    .field("violation", &violation.message)
+                    .emit();
+            }
+        }
+    }
+    for result in &response.results {
+        for violation in &result.violations {
+            if violation.enforcement_action == EnforcementAction::Warn {
+                logging::warn("warning")
+                    .field("object", &result.key)
+                    .field("warning", &violation.message)
+                    .emit();
+            }
+        }
+        for exemption in &result.exemptions {
+            logging::info("exempted")
+                .field("object", &result.key)
+                .field("exemption", &exemption.message)
+                .emit();
+        }
+    }
+    println!("{}", Outcome::from(&response));
+}
+
+/// Backs the `validate` export: reads the objects to test and the constraint
+/// parameters from buffers the host filled, and returns the [`Response`] as
+/// JSON, see [`string_to_host`] for how the host reads it.
+///
+/// # Safety
+///
+/// Both buffers must have been returned by [`allocate`] and filled by the
+/// host. They are owned by the policy from then on, and freed before the
+/// function returns.
+pub unsafe fn validate<P: Policy>(
+    object_ptr: *mut u8,
+    object_size: usize,
+    parameters_ptr: *mut u8,
+    parameters_size: usize,
+) -> u64 {
+    let object_to_test = string_from_host(object_ptr, object_size);
+    let parameters = string_from_host(parameters_ptr, parameters_size);
+    let response = review::<P>(&object_to_test, &parameters);
+    string_to_host(serde_json::to_string(&response).expect("serializing the response"))
+}
+
+/// Backs the `validate_with_inventory` export: same as the `validate` export,
+/// with the inventory in a third buffer, see [`review_with_inventory`].
+///
+/// # Safety
+///
+/// See [`validate`].
+pub unsafe fn validate_with_inventory<P: Policy>(
+    object_ptr: *mut u8,
+    object_size: usize,
+    parameters_ptr: *mut u8,
+    parameters_size: usize,
+    inventory_ptr: *mut u8,
+    inventory_size: usize,
+) -> u64 {
+    let object_to_test = string_from_host(object_ptr, object_size);
+    let parameters = string_from_host(parameters_ptr, parameters_size);
+    let inventory = string_from_host(inventory_ptr, inventory_size);
+    let response = review_with_inventory::<P>(&object_to_test, &parameters, &inventory);
+    string_to_host(serde_json::to_string(&response).expect("serializing the response"))
+}
+
+/// Result of the `mutate` export.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Mutation {
+    /// The JSONPatch to apply to the object, empty when it needs no change.
+    pub patch: Vec<PatchOperation>,
+    /// Why the object could not be mutated. The patch is empty then.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Returns the changes bringing the object in line with the policy, with
+/// the raw constraint parameters. Unlike [`review`], this takes a single
+/// object, as a patch only applies to one.
+pub fn mutation<P: Policy>(object_to_mutate: &str, parameters: &str) -> Mutation {
+    let error = |e: anyhow::Error| Mutation {
+        patch: Vec::new(),
+        error: Some(e.to_string()),
+    };
+    let params = match parse_parameters::<P::Params>(parameters) {
+        Ok(params) => params,
+        Err(e) => return error(e),
+    };
+    let mut requests = match Request::from_documents(object_to_mutate) {
+        Ok(requests) => requests,
+        Err(e) => return error(e),
+    };
+    if requests.len() != 1 {
+        return error(anyhow::anyhow!(
+            "invalid object: expected a single object to mutate, got {}",
+            requests.len()
+        ));
+    }
+    Mutation {
+        patch: P::mutate(&requests.remove(0), &params),
+        error: None,
+    }
+}
+
+/// Backs the `mutate` export: same as the `validate` export, but returns the
+/// [`Mutation`] as JSON.
+///
+/// # Safety
+///
+/// See [`validate`].
+pub unsafe fn mutate<P: Policy>(
+    object_ptr: *mut u8,
+    object_size: usize,
+    parameters_ptr: *mut u8,
+    parameters_size: usize,
+) -> u64 {
+    let object_to_mutate = string_from_host(object_ptr, object_size);
+    let parameters = string_from_host(parameters_ptr, parameters_size);
+    let mutation = mutation::<P>(&object_to_mutate, &parameters);
+    string_to_host(serde_json::to_string(&mutation).expect("serializing the mutation"))
+}
+
+/// Backs the `metadata` export, see [`string_to_host`] for how the host reads
+/// the result.
+pub fn metadata<P: Policy>() -> u64 {
+    string_to_host(P::metadata().to_json())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Metadata;
+    use schemars::JsonSchema;
+    use serde::Deserialize;
+    use serde_json::Value;
+
+    /// Denies every object with the `deny` label, unless it is allowed by
+    /// the parameters.
+    struct DenyLabel;
+
+    #[derive(Default, Deserialize, JsonSchema)]
+    #[serde(deny_unknown_fields, default)]
+    struct Params {
+        allow: bool,
+        warn: bool,
+    }
+
+    impl Policy for DenyLabel {
+        type Params = Params;
+
+        fn metadata() -> Metadata {
+            Metadata {
+                name: "denylabel",
+                title: "Deny label",
+                version: "0.1.0",
+                constraint_kind: "DenyLabel",
+                kinds: vec![],
+                parameters: Value::Null,
+                mutating: false,
+            }
+        }
+
+        fn validate(request: &Request, params: &Params) -> Decision {
+            if request.object()["metadata"]["labels"]["deny"].is_null() {
+                return Decision::allow();
+            }
+            if params.allow {
+                return Decision {
+                    exemptions: vec![Exemption::new("deny label allowed")],
+                    ..Decision::allow()
+                };
+            }
+            if params.warn {
+                return Decision {
+                    violations: vec![
+                        Violation::new("deny label set").with_action(EnforcementAction::Warn)
+                    ],
+                    ..Decision::allow()
+                };
+            }
+            Decision::deny("denied by label")
+        }
+
+        fn mutate(request: &Request, params: &Params) -> Vec<PatchOperation> {
+            let mut patch = crate::Patch::new(request.object().clone());
+            if !params.allow {
+                patch.set("/metadata/labels/deny", Value::Null);
+            }
+            patch.into_operations()
+        }
+    }
+
+    const LABELED: &str = r#"{"kind":"Pod","metadata":{"labels":{"deny":"yes"}}}"#;
+
+    #[test]
+    fn evaluate_test() {
+        assert_eq!(evaluate::<DenyLabel>(LABELED, ""), Outcome::Denied);
+        assert_eq!(
+            evaluate::<DenyLabel>(LABELED, r#"{"allow":true}"#),
+            Outcome::Allowed
+        );
+        assert_eq!(
+            evaluate::<DenyLabel>(r#"{"kind":"Pod"}"#, ""),
+            Outcome::Allowed
+        );
+    }
+
+    #[test]
+    fn review_documents_test() {
+        let response = review::<DenyLabel>(
+            "kind: Pod\nmetadata:\n  name: a\n---\nkind: Pod\nmetadata:\n  name: b\n  labels:\n    deny: yes\n",
+            "",
+        );
+        assert!(!response.allowed, "One denied object must deny the request");
+        let results: Vec<_> = response
+            .results
+            .iter()
+            .map(|r| (r.key.as_str(), r.allowed))
+            .collect();
+        assert_eq!(results, vec![("Pod/a", true), ("Pod/b", false)]);
+    }
+
+    #[test]
+    fn review_reports_exemptions_test() {
+        let response = review::<DenyLabel>(LABELED, r#"{"allow":true}"#);
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            serde_json::json!({
+                "allowed": true,
+                "violations": [],
+                "exemptions": [{"message": "deny label allowed"}],
+                "results": [{
+                    "key": "Pod/",
+                    "allowed": true,
+                    "violations": [],
+                    "exemptions": [{"message": "deny label allowed"}],
+                }],
+            })
+        );
+        assert!(
+            serde_json::to_value(review::<DenyLabel>(LABELED, ""))
+                .unwrap()
+                .get("exemptions")
+                .is_none(),
+            "No exemption must leave the field out"
+        );
+    }
+
+    #[test]
+    fn review_returns_warnings_test() {
+        let response = review::<DenyLabel>(LABELED, r#"{"warn":true}"#);
+        assert!(response.allowed, "A warning must not deny the request");
+        assert_eq!(response.warnings, vec!["deny label set"]);
+        assert_eq!(
+            response.results[0].violations[0].enforcement_action,
+            EnforcementAction::Warn
+        );
+        assert!(review::<DenyLabel>(LABELED, "").warnings.is_empty());
+    }
+
+    #[test]
+    fn review_with_invalid_inventory_test() {
+        let response = review_with_inventory::<DenyLabel>(LABELED, "", r#"{"kind": "Pod"}"#);
+        assert!(response
+            .error
+            .is_some_and(|error| error.starts_with("invalid inventory")));
+        assert_eq!(
+            review_with_inventory::<DenyLabel>(LABELED, "", " "),
+            review::<DenyLabel>(LABELED, ""),
+            "A blank inventory must be the same as none"
+        );
+    }
+
+    #[test]
+    fn mutation_test() {
+        let result = mutation::<DenyLabel>(r#"{"kind":"Pod"}"#, "");
+        assert_eq!(
+            serde_json::to_value(result).unwrap(),
+            serde_json::json!({
+                "patch": [{"op": "add", "path": "/metadata", "value": {"labels": {"deny": null}}}],
+            })
+        );
+
+        let result = mutation::<DenyLabel>("kind: Pod\n---\nkind: Pod\n", "");
+        assert!(result.patch.is_empty());
+        assert!(result
+            .error
+            .unwrap()
+            .contains("expected a single object to mutate, got 2"));
+    }
+
+    #[test]
+    fn evaluate_invalid_object_test() {
+        assert_eq!(evaluate::<DenyLabel>("not json", ""), Outcome::Denied);
+    }
+
+    #[test]
+    fn evaluate_invalid_parameters_test() {
+        let outcome = evaluate::<DenyLabel>(LABELED, r#"{"alow":true}"#);
+        assert!(matches!(outcome, Outcome::InvalidParameters(_)));
+        assert!(outcome.to_string().starts_with("error: invalid parameters"));
+    }
+
+    #[test]
+    fn review_test() {
+        assert_eq!(
+            serde_json::to_value(review::<DenyLabel>(LABELED, "")).unwrap(),
+            serde_json::json!({
+                "allowed": false,
+                "violations": [{"message": "denied by label", "enforcementAction": "deny"}],
+                "results": [{
+                    "key": "Pod/",
+                    "allowed": false,
+                    "violations": [{"message": "denied by label", "enforcementAction": "deny"}],
+                }],
+            })
+        );
+        let response = review::<DenyLabel>(LABELED, r#"{"alow":true}"#);
+        assert!(
+            !response.allowed,
+            "Invalid parameters must not allow the request"
+        );
+        assert!(response
+            .error
+            .unwrap()
+            .starts_with("invalid parameters: unknown field `alow`"));
+    }
+
+    #[test]
+    fn allocate_returns_a_writable_buffer_test() {
+        let ptr = allocate(5);
+        unsafe {
+            std::ptr::copy_nonoverlapping(b"hello".as_ptr(), ptr, 5);
+            assert_eq!(string_from_host(ptr, 5), "hello");
+        }
+    }
+
+    #[test]
+    fn string_to_host_packs_pointer_and_length_test() {
+        let packed = string_to_host("hello".to_string());
+        assert_eq!(packed & 0xffff_ffff, 5);
+    }
+}